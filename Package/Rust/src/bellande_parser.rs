@@ -13,191 +13,7917 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::collections::HashMap;
+//! The parser and writer work on `&str` and `String` and need only `alloc`.
+//! Everything that touches files, the environment or `std::io` is behind the
+//! default `std` feature; without it the crate is `no_std`. Maps are
+//! `BTreeMap`s either way, so enabling `std` never changes a public type.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "std")]
 use std::fs;
+#[cfg(feature = "std")]
+use std::io::{BufRead, Read, Write};
+#[cfg(feature = "std")]
 use std::path::Path;
 
-#[derive(Debug, Clone)]
-enum BellandeValue {
+#[derive(Debug, Clone, PartialEq)]
+pub enum BellandeValue {
     String(String),
     Integer(i64),
     Float(f64),
     Boolean(bool),
     Null,
     List(Vec<BellandeValue>),
-    Map(HashMap<String, BellandeValue>),
+    Map(BTreeMap<String, BellandeValue>),
+    /// The unparsed source of a block below
+    /// [`ParseOptions::max_parse_depth_materialized`], dedented. Turn it into
+    /// values with [`BellandeFormat::materialize`].
+    Raw(String),
 }
 
-pub struct BellandeFormat;
+#[derive(Debug)]
+pub enum BellandeError {
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// A syntax error; `line` and `column` are 1-based.
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    LimitExceeded(String),
+    /// Input that is not valid UTF-8. `offset` is the index of the first
+    /// invalid byte and `context` a hex dump of the bytes around it, with the
+    /// invalid sequence in brackets.
+    InvalidUtf8 {
+        offset: usize,
+        context: String,
+    },
+    MissingPath(String),
+    Validation {
+        path: String,
+        message: String,
+    },
+}
 
-impl BellandeFormat {
-    pub fn parse_bellande<P: AsRef<Path>>(
-        &self,
-        file_path: P,
-    ) -> Result<BellandeValue, std::io::Error> {
-        let content = fs::read_to_string(file_path)?;
-        let lines: Vec<&str> = content.lines().collect();
-        let parsed_data = self.parse_lines(&lines);
-        Ok(parsed_data)
+impl BellandeError {
+    fn parse(line: usize, column: usize, message: impl Into<String>) -> Self {
+        BellandeError::Parse {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for BellandeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            BellandeError::Io(err) => write!(f, "I/O error: {}", err),
+            BellandeError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            BellandeError::LimitExceeded(message) => write!(f, "limit exceeded: {}", message),
+            BellandeError::InvalidUtf8 { offset, context } => {
+                write!(f, "invalid UTF-8 at byte {}: {}", offset, context)
+            }
+            BellandeError::MissingPath(path) => write!(f, "no value at path `{}`", path),
+            BellandeError::Validation { path, message } => write!(f, "`{}`: {}", path, message),
+        }
     }
+}
 
-    fn parse_lines(&self, lines: &[&str]) -> BellandeValue {
-        let mut root = BellandeValue::Map(HashMap::new());
-        let mut stack: Vec<(usize, String)> = vec![(0, String::new())];
+impl core::error::Error for BellandeError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            BellandeError::Io(err) => Some(err),
+            BellandeError::Parse { .. }
+            | BellandeError::LimitExceeded(_)
+            | BellandeError::InvalidUtf8 { .. }
+            | BellandeError::MissingPath(_)
+            | BellandeError::Validation { .. } => None,
+        }
+    }
+}
 
-        for line in lines {
-            let stripped = line.trim();
-            if stripped.is_empty() || stripped.starts_with('#') {
-                continue;
+#[cfg(feature = "std")]
+impl From<std::io::Error> for BellandeError {
+    fn from(err: std::io::Error) -> Self {
+        BellandeError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BellandeWarning {
+    pub line: usize,
+    pub column: usize,
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+/// What a [`BellandeWarning`] is about, for callers that act on some
+/// warnings without matching on their message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningKind {
+    /// A line indented differently from its earlier siblings, which makes
+    /// its nesting ambiguous.
+    IndentationMismatch,
+    /// Indentation made of both tabs and spaces.
+    MixedIndentation,
+    /// A key that replaces an earlier one in the same map.
+    DuplicateKey,
+    /// Extra tokens after a scalar, with [`TrailingContent::Warn`].
+    TrailingContent,
+    /// An unquoted value such as `1,000` that looks numeric but is a string.
+    NumericString,
+    /// A malformed line dropped by [`ParseOptions::lenient`].
+    Skipped,
+}
+
+impl fmt::Display for BellandeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl BellandeValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            BellandeValue::String(_) => "string",
+            BellandeValue::Integer(_) => "integer",
+            BellandeValue::Float(_) => "float",
+            BellandeValue::Boolean(_) => "boolean",
+            BellandeValue::Null => "null",
+            BellandeValue::List(_) => "list",
+            BellandeValue::Map(_) => "map",
+            BellandeValue::Raw(_) => "raw",
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            BellandeValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            BellandeValue::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Returns floats as-is and widens integers.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            BellandeValue::Float(f) => Some(*f),
+            BellandeValue::Integer(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    /// Reads a percentage written as `80%` (or `12.5 %`), returning the number
+    /// before the sign, e.g. `80.0`.
+    pub fn as_percent(&self) -> Option<f64> {
+        let number = self.as_str()?.trim().strip_suffix('%')?.trim_end();
+        number.parse::<f64>().ok().filter(|n| n.is_finite())
+    }
+
+    /// Builds the string form of a percentage, so that
+    /// `BellandeValue::percent(80.0)` is written as `80%`.
+    pub fn percent(value: f64) -> BellandeValue {
+        BellandeValue::String(format!("{}%", value))
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            BellandeValue::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, BellandeValue::Null)
+    }
+
+    pub fn as_list(&self) -> Option<&Vec<BellandeValue>> {
+        match self {
+            BellandeValue::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// Treats a list as its elements and any other value as a list of one,
+    /// for settings that accept either `hosts: a` or `hosts: [a, b]`.
+    pub fn as_list_or_single(&self) -> Vec<&BellandeValue> {
+        match self {
+            BellandeValue::List(list) => list.iter().collect(),
+            single => vec![single],
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&BTreeMap<String, BellandeValue>> {
+        match self {
+            BellandeValue::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    pub fn into_string(self) -> Option<String> {
+        match self {
+            BellandeValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn into_list(self) -> Option<Vec<BellandeValue>> {
+        match self {
+            BellandeValue::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn into_map(self) -> Option<BTreeMap<String, BellandeValue>> {
+        match self {
+            BellandeValue::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Deep-merges `other` into `self`. Maps are merged key by key; any other
+    /// combination of values is resolved by `other` replacing `self`.
+    pub fn merge(&mut self, other: BellandeValue) {
+        self.merge_with(other, &ListStrategy::Replace);
+    }
+
+    /// Like [`BellandeValue::merge`], but combines two lists according to
+    /// `lists`, at any depth.
+    pub fn merge_with(&mut self, other: BellandeValue, lists: &ListStrategy) {
+        match (self, other) {
+            (BellandeValue::Map(base), BellandeValue::Map(overlay)) => {
+                for (key, value) in overlay {
+                    match base.get_mut(&key) {
+                        Some(existing) => existing.merge_with(value, lists),
+                        None => {
+                            base.insert(key, value);
+                        }
+                    }
+                }
+            }
+            (BellandeValue::List(base), BellandeValue::List(overlay)) => match lists {
+                ListStrategy::Replace => *base = overlay,
+                ListStrategy::Concat => base.extend(overlay),
+                ListStrategy::MergeByIndex => {
+                    for (index, item) in overlay.into_iter().enumerate() {
+                        match base.get_mut(index) {
+                            Some(existing) => existing.merge_with(item, lists),
+                            None => base.push(item),
+                        }
+                    }
+                }
+                ListStrategy::MergeByKey(key) => {
+                    for item in overlay {
+                        let item_id = item.as_map().and_then(|map| map.get(key));
+                        let position = base.iter().position(|existing| {
+                            item_id.is_some()
+                                && existing.as_map().and_then(|map| map.get(key)) == item_id
+                        });
+                        match position {
+                            Some(position) => base[position].merge_with(item, lists),
+                            None => base.push(item),
+                        }
+                    }
+                }
+            },
+            (base, overlay) => *base = overlay,
+        }
+    }
+
+    /// Returns the dotted path of the first place where `other` differs from
+    /// `self`, or `None` if they are equal. Maps are searched in sorted key
+    /// order and lists by index, so the answer is the first line that
+    /// [`BellandeValue::diff_report`] would start at; a key or item present
+    /// on one side only is itself the difference. A root that differs in
+    /// type or scalar value gives `""`.
+    pub fn first_diff(&self, other: &BellandeValue) -> Option<String> {
+        self.first_diff_at(other, "")
+    }
+
+    fn first_diff_at(&self, other: &BellandeValue, path: &str) -> Option<String> {
+        match (self, other) {
+            (BellandeValue::Map(old), BellandeValue::Map(new)) => {
+                let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+                keys.sort();
+                keys.dedup();
+                keys.into_iter().find_map(|key| {
+                    let path = Self::join_path(path, key);
+                    match (old.get(key), new.get(key)) {
+                        (Some(old), Some(new)) => old.first_diff_at(new, &path),
+                        _ => Some(path),
+                    }
+                })
             }
+            (BellandeValue::List(old), BellandeValue::List(new)) => (0..old.len().max(new.len()))
+                .find_map(|i| {
+                    let path = Self::join_path(path, &i.to_string());
+                    match (old.get(i), new.get(i)) {
+                        (Some(old), Some(new)) => old.first_diff_at(new, &path),
+                        _ => Some(path),
+                    }
+                }),
+            _ if self == other => None,
+            _ => Some(path.to_string()),
+        }
+    }
 
-            let indent = line.len() - stripped.len();
+    /// Appends `segment` to a dotted `path`; the empty path is the root.
+    fn join_path(path: &str, segment: &str) -> String {
+        if path.is_empty() {
+            segment.to_string()
+        } else {
+            format!("{}.{}", path, segment)
+        }
+    }
 
-            while let Some(&(last_indent, _)) = stack.last() {
-                if indent <= last_indent {
-                    stack.pop();
-                } else {
-                    break;
+    /// Collects every leaf path (see [`BellandeValue::paths`]) where `old`
+    /// and `new` differ, walking both trees by their real keys so keys
+    /// containing `.` are followed correctly. Where the two sides disagree
+    /// on being a map or a list, each side's leaves are compared with
+    /// nothing, and a scalar is compared with whatever the other side holds.
+    fn collect_changes<'a>(
+        old: Option<&'a BellandeValue>,
+        new: Option<&'a BellandeValue>,
+        path: String,
+        changes: &mut Vec<Change<'a>>,
+    ) {
+        match (old, new) {
+            (Some(BellandeValue::Map(old)), Some(BellandeValue::Map(new))) => {
+                let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+                for key in keys {
+                    let path = Self::join_path(&path, key);
+                    Self::collect_changes(old.get(key), new.get(key), path, changes);
+                }
+            }
+            (Some(BellandeValue::List(old)), Some(BellandeValue::List(new))) => {
+                for i in 0..old.len().max(new.len()) {
+                    let path = Self::join_path(&path, &i.to_string());
+                    Self::collect_changes(old.get(i), new.get(i), path, changes);
+                }
+            }
+            _ if old == new => {}
+            _ => {
+                let is_leaf = |value: Option<&BellandeValue>| {
+                    value.is_some_and(|value| match value {
+                        BellandeValue::Map(map) => map.is_empty(),
+                        BellandeValue::List(list) => list.is_empty(),
+                        _ => true,
+                    })
+                };
+                if is_leaf(old) || is_leaf(new) {
+                    changes.push((path.clone(), old, new));
+                }
+                for (side, is_old) in [(old, true), (new, false)] {
+                    let children: Vec<(String, &BellandeValue)> = match side {
+                        Some(BellandeValue::Map(map)) => {
+                            map.iter().map(|(k, v)| (k.clone(), v)).collect()
+                        }
+                        Some(BellandeValue::List(list)) => list
+                            .iter()
+                            .enumerate()
+                            .map(|(i, v)| (i.to_string(), v))
+                            .collect(),
+                        _ => Vec::new(),
+                    };
+                    for (segment, child) in children {
+                        let path = Self::join_path(&path, &segment);
+                        if is_old {
+                            Self::collect_changes(Some(child), None, path, changes);
+                        } else {
+                            Self::collect_changes(None, Some(child), path, changes);
+                        }
+                    }
                 }
             }
+        }
+    }
 
-            if let Some(colon_pos) = stripped.find(':') {
-                let (key, value) = stripped.split_at(colon_pos);
-                let key = key.trim().to_string();
-                let value = value[1..].trim();
+    /// Describes how `other` differs from `self`, one line per change with a
+    /// `\n` after each: `~ server.port: 80 -> 443` for a changed value,
+    /// `+ server.tls: true` for an added one and `- legacy.flag` for a removed
+    /// one. Maps are compared key by key in sorted order and lists index by
+    /// index; an added or removed map or list is listed leaf by leaf (see
+    /// [`BellandeValue::paths`]). Equal values give an empty report.
+    pub fn diff_report(&self, other: &BellandeValue) -> String {
+        let mut report = String::new();
+        Self::diff_into(self, other, "", &mut report);
+        report
+    }
 
-                if !value.is_empty() {
-                    let parsed_value = self.parse_value(value);
-                    self.insert_value(&mut root, &stack, &key, parsed_value);
-                } else {
-                    let new_list = BellandeValue::List(Vec::new());
-                    self.insert_value(&mut root, &stack, &key, new_list);
-                    stack.push((indent, key));
+    fn diff_into(old: &BellandeValue, new: &BellandeValue, path: &str, report: &mut String) {
+        let children: Vec<(String, Option<&BellandeValue>, Option<&BellandeValue>)> =
+            match (old, new) {
+                (BellandeValue::Map(old), BellandeValue::Map(new)) => {
+                    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+                    keys.sort();
+                    keys.dedup();
+                    keys.into_iter()
+                        .map(|key| (Self::join_path(path, key), old.get(key), new.get(key)))
+                        .collect()
+                }
+                (BellandeValue::List(old), BellandeValue::List(new)) => {
+                    (0..old.len().max(new.len()))
+                        .map(|i| {
+                            (
+                                Self::join_path(path, &i.to_string()),
+                                old.get(i),
+                                new.get(i),
+                            )
+                        })
+                        .collect()
+                }
+                _ if old == new => return,
+                _ => {
+                    let is_nested = |value: &BellandeValue| match value {
+                        BellandeValue::Map(map) => !map.is_empty(),
+                        BellandeValue::List(list) => !list.is_empty(),
+                        _ => false,
+                    };
+                    if is_nested(old) || is_nested(new) {
+                        vec![
+                            (path.to_string(), Some(old), None),
+                            (path.to_string(), None, Some(new)),
+                        ]
+                    } else {
+                        let name = if path.is_empty() { "(root)" } else { path };
+                        report.push_str(&format!(
+                            "~ {}: {} -> {}\n",
+                            name,
+                            Self::diff_text(old),
+                            Self::diff_text(new)
+                        ));
+                        return;
+                    }
+                }
+            };
+
+        for (child_path, old, new) in children {
+            let leaves = |value| {
+                BellandeValue::leaf_entries(value)
+                    .into_iter()
+                    .map(|(leaf, value)| {
+                        let full = match (child_path.is_empty(), leaf.is_empty()) {
+                            (true, _) => leaf,
+                            (false, true) => child_path.clone(),
+                            (false, false) => format!("{}.{}", child_path, leaf),
+                        };
+                        (full, value)
+                    })
+            };
+            match (old, new) {
+                (Some(old), Some(new)) => Self::diff_into(old, new, &child_path, report),
+                (Some(old), None) => {
+                    for (full, _) in leaves(old) {
+                        report.push_str(&format!("- {}\n", full));
+                    }
                 }
-            } else if stripped.starts_with('-') {
-                let value = stripped[1..].trim();
-                let parsed_value = self.parse_value(value);
-                if let Some((_, key)) = stack.last() {
-                    self.append_to_list(&mut root, &stack, key, parsed_value);
+                (None, Some(new)) => {
+                    for (full, value) in leaves(new) {
+                        report.push_str(&format!("+ {}: {}\n", full, Self::diff_text(value)));
+                    }
                 }
+                (None, None) => {}
             }
         }
+    }
 
-        root
+    fn diff_text(value: &BellandeValue) -> String {
+        match value {
+            BellandeValue::Map(_) => "{}".to_string(),
+            BellandeValue::List(_) => "[]".to_string(),
+            scalar => BellandeFormat.format_value(scalar, &WriteOptions::default()),
+        }
     }
 
-    fn insert_value(
-        &self,
-        root: &mut BellandeValue,
-        stack: &[(usize, String)],
-        key: &str,
-        value: BellandeValue,
-    ) {
-        let mut current = root;
-        for (_, path_key) in stack.iter().skip(1) {
-            if let BellandeValue::Map(map) = current {
-                current = map.get_mut(path_key).unwrap();
+    /// Returns the sparse tree of what changed from `base` to `self`, such
+    /// that `base.apply_merge_patch(&patch)` yields `self`. Unchanged map
+    /// entries are left out and maps are compared key by key; any other
+    /// changed value, including a list, is sent whole. A key that exists only
+    /// in `base` is sent as `null`, which [`BellandeValue::apply_merge_patch`]
+    /// takes as a deletion. As in RFC 7386, a key set to an explicit `null`
+    /// in `self` therefore cannot be told apart from a removed one: applying
+    /// the patch drops it rather than keeping the `null`.
+    pub fn to_patch(&self, base: &BellandeValue) -> BellandeValue {
+        match (self, base) {
+            (BellandeValue::Map(current), BellandeValue::Map(base)) => {
+                let mut patch: BTreeMap<String, BellandeValue> = current
+                    .iter()
+                    .filter_map(|(key, value)| match base.get(key) {
+                        Some(old) if old == value => None,
+                        Some(old) => Some((key.clone(), value.to_patch(old))),
+                        None => Some((key.clone(), value.clone())),
+                    })
+                    .collect();
+                for key in base.keys().filter(|key| !current.contains_key(*key)) {
+                    patch.insert(key.clone(), BellandeValue::Null);
+                }
+                BellandeValue::Map(patch)
+            }
+            (current, _) => current.clone(),
+        }
+    }
+
+    /// Applies `patch` with JSON Merge Patch semantics (RFC 7386): a map
+    /// patch is applied key by key, with `null` removing the key and any
+    /// other value merged in recursively, turning a non-map `self` into a
+    /// map first. Any other patch, a list included, replaces `self` whole.
+    /// Unlike [`BellandeValue::merge`], this can delete keys, and
+    /// [`BellandeValue::to_patch`] produces patches in this form.
+    pub fn apply_merge_patch(&mut self, patch: &BellandeValue) {
+        let BellandeValue::Map(patch) = patch else {
+            *self = patch.clone();
+            return;
+        };
+        if !matches!(self, BellandeValue::Map(_)) {
+            *self = BellandeValue::Map(BTreeMap::new());
+        }
+        let BellandeValue::Map(target) = self else {
+            return;
+        };
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                target
+                    .entry(key.clone())
+                    .or_insert(BellandeValue::Null)
+                    .apply_merge_patch(value);
             }
         }
-        if let BellandeValue::Map(map) = current {
-            map.insert(key.to_string(), value);
+    }
+
+    /// Looks up a value by a dotted path such as `server.port` or
+    /// `projects.0.name`; numeric segments index into lists. The empty path
+    /// refers to `self`.
+    pub fn get_path(&self, path: &str) -> Option<&BellandeValue> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        let mut current = self;
+        for segment in path.split('.') {
+            current = match current {
+                BellandeValue::Map(map) => map.get(segment)?,
+                BellandeValue::List(list) => list.get(segment.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
         }
+        Some(current)
+    }
+
+    /// Like [`BellandeValue::get_path`], but fails with
+    /// [`BellandeError::MissingPath`] instead of returning `None`.
+    pub fn get_required(&self, path: &str) -> Result<&BellandeValue, BellandeError> {
+        self.get_path(path)
+            .ok_or_else(|| BellandeError::MissingPath(path.to_string()))
     }
 
-    fn append_to_list(
+    /// Returns the value at `path` if its [`BellandeValue::type_name`] is
+    /// `ty`, e.g. `"map"` or `"list"`. A missing path fails like
+    /// [`BellandeValue::get_required`]; any other type fails with a
+    /// [`BellandeError::Validation`] naming the type that was found.
+    pub fn expect_type(&self, path: &str, ty: &str) -> Result<&BellandeValue, BellandeError> {
+        self.get_typed(path, ty, |value| (value.type_name() == ty).then_some(value))
+    }
+
+    /// Checks every `(path, type)` requirement with
+    /// [`BellandeValue::expect_type`] and returns the values in the same
+    /// order, or every failure at once rather than only the first.
+    pub fn expect_types(
         &self,
-        root: &mut BellandeValue,
-        stack: &[(usize, String)],
-        key: &str,
-        value: BellandeValue,
-    ) {
-        let mut current = root;
-        for (_, path_key) in stack.iter().skip(1) {
-            if let BellandeValue::Map(map) = current {
-                current = map.get_mut(path_key).unwrap();
+        requirements: &[(&str, &str)],
+    ) -> Result<Vec<&BellandeValue>, Vec<BellandeError>> {
+        let mut values = Vec::with_capacity(requirements.len());
+        let mut errors = Vec::new();
+        for (path, ty) in requirements {
+            match self.expect_type(path, ty) {
+                Ok(value) => values.push(value),
+                Err(err) => errors.push(err),
             }
         }
-        if let BellandeValue::Map(map) = current {
-            if let Some(BellandeValue::List(list)) = map.get_mut(key) {
-                list.push(value);
-            }
+        if errors.is_empty() {
+            Ok(values)
+        } else {
+            Err(errors)
         }
     }
 
-    fn parse_value(&self, value: &str) -> BellandeValue {
-        if value.eq_ignore_ascii_case("true") {
-            BellandeValue::Boolean(true)
-        } else if value.eq_ignore_ascii_case("false") {
-            BellandeValue::Boolean(false)
-        } else if value.eq_ignore_ascii_case("null") {
-            BellandeValue::Null
-        } else if value.starts_with('"') && value.ends_with('"') {
-            BellandeValue::String(value[1..value.len() - 1].to_string())
-        } else if let Ok(int_value) = value.parse::<i64>() {
-            BellandeValue::Integer(int_value)
-        } else if let Ok(float_value) = value.parse::<f64>() {
-            BellandeValue::Float(float_value)
+    /// Checks that the value has the structure and types of `reference`, a
+    /// known-good example, and returns every mismatch, ordered by path. Maps
+    /// must have exactly the reference's keys: a missing key is a
+    /// [`BellandeError::MissingPath`] and an extra one a
+    /// [`BellandeError::Validation`]. Each list item is checked against the
+    /// reference's first item, so an empty reference list accepts any items.
+    /// An integer matches a float, and a null in the reference matches
+    /// anything.
+    pub fn matches_shape(&self, reference: &BellandeValue) -> Result<(), Vec<BellandeError>> {
+        let mut errors = Vec::new();
+        Self::shape_errors(self, reference, String::new(), &mut errors);
+        if errors.is_empty() {
+            Ok(())
         } else {
-            BellandeValue::String(value.to_string())
+            Err(errors)
         }
     }
 
-    pub fn write_bellande<P: AsRef<Path>>(
-        &self,
-        data: &BellandeValue,
-        file_path: P,
-    ) -> Result<(), std::io::Error> {
-        let content = self.to_bellande_string(data, 0);
-        fs::write(file_path, content)
+    fn shape_errors(
+        value: &BellandeValue,
+        reference: &BellandeValue,
+        path: String,
+        errors: &mut Vec<BellandeError>,
+    ) {
+        match (value, reference) {
+            (_, BellandeValue::Null) | (BellandeValue::Integer(_), BellandeValue::Float(_)) => {}
+            (BellandeValue::Map(map), BellandeValue::Map(expected)) => {
+                let keys: BTreeSet<&String> = map.keys().chain(expected.keys()).collect();
+                for key in keys {
+                    match (map.get(key), expected.get(key)) {
+                        (Some(value), Some(reference)) => Self::shape_errors(
+                            value,
+                            reference,
+                            Self::join_path(&path, key),
+                            errors,
+                        ),
+                        (None, _) => {
+                            errors.push(BellandeError::MissingPath(Self::join_path(&path, key)))
+                        }
+                        (Some(_), None) => errors.push(BellandeError::Validation {
+                            path: Self::join_path(&path, key),
+                            message: "key is not in the reference".to_string(),
+                        }),
+                    }
+                }
+            }
+            (BellandeValue::List(list), BellandeValue::List(expected)) => {
+                if let Some(reference) = expected.first() {
+                    for (index, item) in list.iter().enumerate() {
+                        Self::shape_errors(
+                            item,
+                            reference,
+                            Self::join_path(&path, &index.to_string()),
+                            errors,
+                        );
+                    }
+                }
+            }
+            _ if value.type_name() == reference.type_name() => {}
+            _ => errors.push(BellandeError::Validation {
+                path,
+                message: format!(
+                    "expected {}, found {}",
+                    reference.type_name(),
+                    value.type_name()
+                ),
+            }),
+        }
     }
 
-    fn to_bellande_string(&self, data: &BellandeValue, indent: usize) -> String {
-        match data {
-            BellandeValue::Map(map) => map
-                .iter()
-                .map(|(key, value)| {
-                    let value_str = match value {
-                        BellandeValue::Map(_) | BellandeValue::List(_) => {
-                            format!("\n{}", self.to_bellande_string(value, indent + 2))
+    pub fn get_str(&self, path: &str) -> Result<&str, BellandeError> {
+        self.get_typed(path, "string", BellandeValue::as_str)
+    }
+
+    pub fn get_i64(&self, path: &str) -> Result<i64, BellandeError> {
+        self.get_typed(path, "integer", BellandeValue::as_i64)
+    }
+
+    /// Accepts integers as well, like [`BellandeValue::as_f64`].
+    pub fn get_f64(&self, path: &str) -> Result<f64, BellandeError> {
+        self.get_typed(path, "number", BellandeValue::as_f64)
+    }
+
+    pub fn get_bool(&self, path: &str) -> Result<bool, BellandeError> {
+        self.get_typed(path, "boolean", BellandeValue::as_bool)
+    }
+
+    /// Reports a value of the wrong type as a [`BellandeError::Validation`]
+    /// naming both the expected and the actual type.
+    fn get_typed<'a, T>(
+        &'a self,
+        path: &str,
+        expected: &str,
+        convert: impl Fn(&'a BellandeValue) -> Option<T>,
+    ) -> Result<T, BellandeError> {
+        let value = self.get_required(path)?;
+        convert(value).ok_or_else(|| BellandeError::Validation {
+            path: path.to_string(),
+            message: format!("expected {}, found {}", expected, value.type_name()),
+        })
+    }
+
+    /// Returns the dotted path of every leaf, map keys in sorted order and list
+    /// items by index. Leaves are scalars and empty lists or maps; list
+    /// elements use their index as the segment, so each path can be passed
+    /// back to [`BellandeValue::get_path`].
+    pub fn paths(&self) -> Vec<String> {
+        self.leaf_entries()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    /// The leaves of [`BellandeValue::paths`] with their values, found by
+    /// walking the tree rather than looking each path up again.
+    fn leaf_entries(&self) -> Vec<(String, &BellandeValue)> {
+        let mut paths = Vec::new();
+        let mut pending = vec![(String::new(), self)];
+        while let Some((path, value)) = pending.pop() {
+            let children: Vec<(String, &BellandeValue)> = match value {
+                BellandeValue::Map(map) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+                BellandeValue::List(list) => list
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| (i.to_string(), v))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            if children.is_empty() {
+                paths.push((path, value));
+                continue;
+            }
+            for (segment, child) in children.into_iter().rev() {
+                pending.push((Self::join_path(&path, &segment), child));
+            }
+        }
+        paths
+    }
+
+    /// Yields every scalar with its dotted path (see
+    /// [`BellandeValue::paths`]), skipping maps and lists, empty ones
+    /// included. Map keys come in sorted order and list items by index; the
+    /// tree is walked lazily without recursion.
+    pub fn leaves(&self) -> impl Iterator<Item = (String, &BellandeValue)> {
+        let mut pending = vec![(String::new(), self)];
+        core::iter::from_fn(move || {
+            while let Some((path, value)) = pending.pop() {
+                match value {
+                    BellandeValue::Map(map) => pending.extend(
+                        map.iter()
+                            .rev()
+                            .map(|(key, child)| (Self::join_path(&path, key), child)),
+                    ),
+                    BellandeValue::List(list) => pending.extend(
+                        list.iter()
+                            .enumerate()
+                            .rev()
+                            .map(|(i, child)| (Self::join_path(&path, &i.to_string()), child)),
+                    ),
+                    scalar => return Some((path, scalar)),
+                }
+            }
+            None
+        })
+    }
+
+    /// Every string in the tree, in the order of [`BellandeValue::leaves`].
+    pub fn all_strings(&self) -> Vec<&str> {
+        self.leaves()
+            .filter_map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Every integer in the tree, in the order of [`BellandeValue::leaves`].
+    pub fn all_integers(&self) -> Vec<i64> {
+        self.leaves()
+            .filter_map(|(_, value)| value.as_i64())
+            .collect()
+    }
+
+    /// Every float in the tree, in the order of [`BellandeValue::leaves`].
+    /// Unlike [`BellandeValue::as_f64`], integers are not included.
+    pub fn all_floats(&self) -> Vec<f64> {
+        self.leaves()
+            .filter_map(|(_, value)| match value {
+                BellandeValue::Float(f) => Some(*f),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Every boolean in the tree, in the order of [`BellandeValue::leaves`].
+    pub fn all_booleans(&self) -> Vec<bool> {
+        self.leaves()
+            .filter_map(|(_, value)| value.as_bool())
+            .collect()
+    }
+
+    /// Returns every map entry whose own key satisfies `pred`, at any depth,
+    /// as `(dotted path, value)` pairs in tree order: a map entry before its
+    /// children, map keys in sorted order and list items by index. List
+    /// indices are part of the path but are never passed to `pred`.
+    pub fn find_keys<F: Fn(&str) -> bool>(&self, pred: F) -> Vec<(String, &BellandeValue)> {
+        let mut found = Vec::new();
+        let mut pending: Vec<(String, &BellandeValue, Option<&str>)> =
+            vec![(String::new(), self, None)];
+        while let Some((path, value, key)) = pending.pop() {
+            match value {
+                BellandeValue::Map(map) => {
+                    for (key, child) in map.iter().rev() {
+                        pending.push((Self::join_path(&path, key), child, Some(key)));
+                    }
+                }
+                BellandeValue::List(list) => {
+                    for (i, child) in list.iter().enumerate().rev() {
+                        pending.push((Self::join_path(&path, &i.to_string()), child, None));
+                    }
+                }
+                _ => {}
+            }
+            if key.is_some_and(&pred) {
+                found.push((path, value));
+            }
+        }
+        found
+    }
+
+    /// Flattens the tree into environment variables such as
+    /// `APP_SERVER_PORT=8080`, sorted by name. Path segments (list indices
+    /// included) are uppercased and joined with `_` after `prefix`, and any
+    /// character that is not ASCII alphanumeric becomes `_`. Strings are
+    /// exported verbatim, other scalars as the writer spells them, and `null`
+    /// as an empty string; empty lists and maps produce no variable.
+    pub fn to_env(&self, prefix: &str) -> Vec<(String, String)> {
+        let mut vars: Vec<(String, String)> = self
+            .leaves()
+            .map(|(path, value)| {
+                let value = match value {
+                    BellandeValue::String(s) => s.clone(),
+                    BellandeValue::Null => String::new(),
+                    scalar => BellandeFormat.format_value(scalar, &WriteOptions::default()),
+                };
+                let name = core::iter::once(prefix)
+                    .chain(path.split('.'))
+                    .filter(|segment| !segment.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("_")
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() {
+                            c.to_ascii_uppercase()
+                        } else {
+                            '_'
                         }
-                        _ => format!(" {}", self.format_value(value)),
-                    };
-                    format!("{}{}: {}", " ".repeat(indent), key, value_str)
-                })
-                .collect::<Vec<_>>()
-                .join("\n"),
-            BellandeValue::List(list) => list
-                .iter()
-                .map(|item| {
-                    format!(
-                        "{}- {}",
-                        " ".repeat(indent),
-                        self.to_bellande_string(item, indent + 2)
-                    )
-                })
-                .collect::<Vec<_>>()
-                .join("\n"),
-            _ => self.format_value(data),
+                    })
+                    .collect();
+                (name, value)
+            })
+            .collect();
+        vars.sort();
+        vars
+    }
+
+    /// Flattens the tree into `(dotted path, text)` pairs for structured
+    /// logging, e.g. `("server.port", "8080")`, in the order of
+    /// [`BellandeValue::paths`]. Strings are logged verbatim and other
+    /// scalars as the writer spells them; empty lists and maps are kept as
+    /// `[]` and `{}` so every leaf shows up.
+    pub fn to_log_fields(&self) -> Vec<(String, String)> {
+        self.leaf_entries()
+            .into_iter()
+            .map(|(path, value)| {
+                let value = match value {
+                    BellandeValue::String(s) => s.clone(),
+                    BellandeValue::List(_) => "[]".to_string(),
+                    BellandeValue::Map(_) => "{}".to_string(),
+                    scalar => BellandeFormat.format_value(scalar, &WriteOptions::default()),
+                };
+                (path, value)
+            })
+            .collect()
+    }
+
+    /// Returns a copy with every scalar replaced by its text, for consumers
+    /// that only take strings such as environment variables or templates.
+    /// Strings are kept as they are and other scalars are spelled as the
+    /// writer spells them (`8080`, `1.5`, `true`, `null`). Maps and lists
+    /// keep their shape.
+    pub fn stringify_leaves(&self) -> BellandeValue {
+        match self {
+            BellandeValue::Map(map) => BellandeValue::Map(
+                map.iter()
+                    .map(|(key, value)| (key.clone(), value.stringify_leaves()))
+                    .collect(),
+            ),
+            BellandeValue::List(list) => {
+                BellandeValue::List(list.iter().map(BellandeValue::stringify_leaves).collect())
+            }
+            BellandeValue::String(s) => BellandeValue::String(s.clone()),
+            scalar => {
+                BellandeValue::String(BellandeFormat.format_value(scalar, &WriteOptions::default()))
+            }
         }
     }
 
-    fn format_value(&self, value: &BellandeValue) -> String {
+    /// Serializes the value as canonical JSON in the style of RFC 8785 (JCS),
+    /// suitable as signing input: no whitespace, map keys sorted by their
+    /// UTF-16 code units, floats in the shortest ECMAScript form (`1.5`,
+    /// `1e+21`, `-0.0` as `0`) and strings escaped minimally. Integers are
+    /// written exactly, even beyond the 2^53 that a JSON double can hold.
+    /// JSON has no NaN or infinity, so such floats become `null`.
+    pub fn canonical_json(&self) -> String {
+        let mut out = String::new();
+        Self::write_canonical_json(self, &mut out);
+        out
+    }
+
+    /// A 64-bit FNV-1a hash of [`BellandeValue::canonical_json`], so two
+    /// loads of a config can be compared for a semantic change without
+    /// keeping the old tree. Key order, formatting and comments do not
+    /// affect it. It is stable across runs and platforms, but as with
+    /// canonical JSON an integer and a float of the same value, or any two
+    /// non-finite floats, hash alike. Not collision resistant; see
+    /// `content_sha256` for that.
+    pub fn content_hash(&self) -> u64 {
+        self.canonical_json()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    /// The SHA-256 digest of [`BellandeValue::canonical_json`], for when a
+    /// hash is stored or compared across trust boundaries.
+    #[cfg(feature = "sha2")]
+    pub fn content_sha256(&self) -> [u8; 32] {
+        use sha2::Digest;
+        sha2::Sha256::digest(self.canonical_json().as_bytes()).into()
+    }
+
+    fn write_canonical_json(value: &BellandeValue, out: &mut String) {
         match value {
-            BellandeValue::String(s) => {
-                if s.contains(' ')
-                    || s.contains(':')
-                    || ["true", "false", "null"].contains(&s.to_lowercase().as_str())
-                {
-                    format!("\"{}\"", s)
-                } else {
-                    s.clone()
+            BellandeValue::String(s) | BellandeValue::Raw(s) => {
+                Self::write_canonical_string(s, out)
+            }
+            BellandeValue::Integer(i) => out.push_str(&i.to_string()),
+            BellandeValue::Float(f) => out.push_str(&Self::canonical_number(*f)),
+            BellandeValue::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+            BellandeValue::Null => out.push_str("null"),
+            BellandeValue::List(list) => {
+                out.push('[');
+                for (i, item) in list.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    Self::write_canonical_json(item, out);
                 }
+                out.push(']');
             }
-            BellandeValue::Integer(i) => i.to_string(),
-            BellandeValue::Float(f) => f.to_string(),
-            BellandeValue::Boolean(b) => b.to_string().to_lowercase(),
-            BellandeValue::Null => "null".to_string(),
-            BellandeValue::List(_) | BellandeValue::Map(_) => unreachable!(),
+            BellandeValue::Map(map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by(|a, b| a.0.encode_utf16().cmp(b.0.encode_utf16()));
+                out.push('{');
+                for (i, (key, value)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    Self::write_canonical_string(key, out);
+                    out.push(':');
+                    Self::write_canonical_json(value, out);
+                }
+                out.push('}');
+            }
+        }
+    }
+
+    fn write_canonical_string(s: &str, out: &mut String) {
+        out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\u{8}' => out.push_str("\\b"),
+                '\t' => out.push_str("\\t"),
+                '\n' => out.push_str("\\n"),
+                '\u{c}' => out.push_str("\\f"),
+                '\r' => out.push_str("\\r"),
+                c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+
+    /// Formats a float like ECMAScript's `Number.prototype.toString`, which
+    /// RFC 8785 prescribes.
+    fn canonical_number(f: f64) -> String {
+        if !f.is_finite() {
+            return "null".to_string();
+        }
+        if f == 0.0 {
+            return "0".to_string();
+        }
+        // `{:e}` yields the shortest round-tripping digits, e.g. `1.2345e-7`.
+        let scientific = format!("{:e}", f.abs());
+        let (mantissa, exponent) = scientific.split_once('e').unwrap();
+        let digits = mantissa.replace('.', "");
+        let k = digits.len() as i32;
+        let n = exponent.parse::<i32>().unwrap() + 1;
+
+        let mut out = String::new();
+        if f < 0.0 {
+            out.push('-');
+        }
+        if k <= n && n <= 21 {
+            out.push_str(&digits);
+            out.push_str(&"0".repeat((n - k) as usize));
+        } else if 0 < n && n <= 21 {
+            out.push_str(&digits[..n as usize]);
+            out.push('.');
+            out.push_str(&digits[n as usize..]);
+        } else if -6 < n && n <= 0 {
+            out.push_str("0.");
+            out.push_str(&"0".repeat(-n as usize));
+            out.push_str(&digits);
+        } else {
+            out.push_str(&digits[..1]);
+            if k > 1 {
+                out.push('.');
+                out.push_str(&digits[1..]);
+            }
+            out.push_str(&format!(
+                "e{}{}",
+                if n > 0 { "+" } else { "-" },
+                (n - 1).abs()
+            ));
+        }
+        out
+    }
+
+    /// Sets the value at a dotted path (see [`BellandeValue::get_path`]),
+    /// creating intermediate maps as needed; a null or placeholder node on
+    /// the way is replaced by a map. A list index may be at most the list's
+    /// length, in which case the value is appended.
+    pub fn set_path(&mut self, path: &str, value: BellandeValue) -> Result<(), BellandeError> {
+        if path.is_empty() {
+            *self = value;
+            return Ok(());
+        }
+        let fail = |message: &str| BellandeError::Validation {
+            path: path.to_string(),
+            message: message.to_string(),
+        };
+        let mut current = self;
+        let mut segments = path.split('.').peekable();
+        while let Some(segment) = segments.next() {
+            let placeholder = match current {
+                BellandeValue::Null => true,
+                BellandeValue::List(list) => list.is_empty() && segment.parse::<usize>().is_err(),
+                _ => false,
+            };
+            if placeholder {
+                *current = BellandeValue::Map(BTreeMap::new());
+            }
+            let last = segments.peek().is_none();
+            current = match current {
+                BellandeValue::Map(map) => {
+                    if last {
+                        map.insert(segment.to_string(), value);
+                        return Ok(());
+                    }
+                    map.entry(segment.to_string())
+                        .or_insert(BellandeValue::Null)
+                }
+                BellandeValue::List(list) => {
+                    let index = segment
+                        .parse::<usize>()
+                        .map_err(|_| fail("expected a list index"))?;
+                    if index > list.len() {
+                        return Err(fail("list index out of range"));
+                    }
+                    if index == list.len() {
+                        list.push(BellandeValue::Null);
+                    }
+                    if last {
+                        list[index] = value;
+                        return Ok(());
+                    }
+                    &mut list[index]
+                }
+                scalar => {
+                    return Err(fail(&format!(
+                        "cannot set a path inside a {}",
+                        scalar.type_name()
+                    )))
+                }
+            };
         }
+        Ok(())
+    }
+
+    /// Applies each `(path, value)` pair with [`BellandeValue::set_path`] in
+    /// order, stopping at the first failure. Patches before the failing one
+    /// stay applied.
+    pub fn apply_patches(
+        &mut self,
+        patches: &[(String, BellandeValue)],
+    ) -> Result<(), BellandeError> {
+        for (path, value) in patches {
+            self.set_path(path, value.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Writes only the node at `path` (see [`BellandeValue::get_path`]) to
+    /// `file_path`, serialized as a document of its own.
+    #[cfg(feature = "std")]
+    pub fn write_subtree<P: AsRef<Path>>(
+        &self,
+        path: &str,
+        file_path: P,
+    ) -> Result<(), BellandeError> {
+        let subtree = self
+            .get_path(path)
+            .ok_or_else(|| BellandeError::MissingPath(path.to_string()))?;
+        BellandeFormat.write_bellande(subtree, file_path)?;
+        Ok(())
+    }
+
+    /// Splits a root map into one document per top-level key, e.g. for
+    /// writing each section to its own file with
+    /// [`BellandeValue::write_subtree`]. Any other root has no sections and
+    /// gives an empty map.
+    pub fn split_sections(&self) -> BTreeMap<String, BellandeValue> {
+        match self {
+            BellandeValue::Map(map) => map.clone(),
+            _ => BTreeMap::new(),
+        }
+    }
+
+    /// The inverse of [`BellandeValue::split_sections`]: a root map with each
+    /// section under its name.
+    pub fn combine_sections(sections: BTreeMap<String, BellandeValue>) -> BellandeValue {
+        BellandeValue::Map(sections)
+    }
+
+    /// Returns `self` with any key missing from it filled in from `defaults`,
+    /// recursively; values present in `self` always win.
+    pub fn with_defaults(&self, defaults: &BellandeValue) -> BellandeValue {
+        let mut merged = defaults.clone();
+        merged.merge(self.clone());
+        merged
+    }
+
+    /// Writes only what differs from `defaults`, the inverse of
+    /// [`BellandeValue::with_defaults`]: the result read back and given the
+    /// same defaults yields `self`. Maps are compared key by key at every
+    /// depth, other values whole; a `null` that replaces a default is kept.
+    /// Keys present only in `defaults` cannot be expressed and are left out.
+    pub fn to_bellande_string_minimal(&self, defaults: &BellandeValue) -> String {
+        match self.minimal_overlay(defaults) {
+            Some(overlay) => {
+                BellandeFormat.to_bellande_string(&overlay, 0, &WriteOptions::default())
+            }
+            None => String::new(),
+        }
+    }
+
+    fn minimal_overlay(&self, defaults: &BellandeValue) -> Option<BellandeValue> {
+        match (self, defaults) {
+            (BellandeValue::Map(current), BellandeValue::Map(defaults)) => {
+                let overlay: BTreeMap<String, BellandeValue> = current
+                    .iter()
+                    .filter_map(|(key, value)| match defaults.get(key) {
+                        Some(default) => value
+                            .minimal_overlay(default)
+                            .map(|overlay| (key.clone(), overlay)),
+                        None => Some((key.clone(), value.clone())),
+                    })
+                    .collect();
+                (!overlay.is_empty()).then_some(BellandeValue::Map(overlay))
+            }
+            (current, default) => (current != default).then(|| current.clone()),
+        }
+    }
+
+    /// Returns a copy with `${NAME}` and `${NAME:-fallback}` references in
+    /// strings replaced from the environment. Unset variables without a
+    /// fallback expand to an empty string. A string that consists of a single
+    /// reference is re-typed after expansion, so `port: ${PORT}` can become an
+    /// integer.
+    #[cfg(feature = "std")]
+    pub fn expand_env_vars(&self) -> BellandeValue {
+        match self {
+            BellandeValue::String(s) if s.contains("${") => {
+                let expanded = Self::expand_env_str(s);
+                let whole_reference =
+                    s.starts_with("${") && s.ends_with('}') && s.find('}') == Some(s.len() - 1);
+                if whole_reference {
+                    BellandeFormat.parse_value(&expanded)
+                } else {
+                    BellandeValue::String(expanded)
+                }
+            }
+            BellandeValue::Map(map) => BellandeValue::Map(
+                map.iter()
+                    .map(|(key, value)| (key.clone(), value.expand_env_vars()))
+                    .collect(),
+            ),
+            BellandeValue::List(list) => {
+                BellandeValue::List(list.iter().map(BellandeValue::expand_env_vars).collect())
+            }
+            other => other.clone(),
+        }
+    }
+
+    /// Returns the environment variable `env_var`, typed like an unquoted
+    /// value, if it is set, and otherwise a copy of the value at the dotted
+    /// `path` (see [`BellandeValue::get_path`]). A variable that is set but
+    /// empty or not valid Unicode still wins, as an empty string.
+    #[cfg(feature = "std")]
+    pub fn get_with_env_override(&self, path: &str, env_var: &str) -> Option<BellandeValue> {
+        match std::env::var_os(env_var) {
+            Some(value) => Some(BellandeFormat.parse_value(value.to_str().unwrap_or(""))),
+            None => self.get_path(path).cloned(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn expand_env_str(s: &str) -> String {
+        let mut out = String::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("${") {
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            out.push_str(&rest[..start]);
+            let reference = &rest[start + 2..start + len];
+            let (name, fallback) = match reference.split_once(":-") {
+                Some((name, fallback)) => (name, Some(fallback)),
+                None => (reference, None),
+            };
+            match std::env::var(name) {
+                Ok(value) => out.push_str(&value),
+                Err(_) => out.push_str(fallback.unwrap_or("")),
+            }
+            rest = &rest[start + len + 1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// Returns a copy with `${path.to.key}` references in strings replaced by
+    /// the value at that dotted path of this same tree (see
+    /// [`BellandeValue::get_path`]). A string that is a single reference
+    /// takes the referenced value itself, map or list included; otherwise
+    /// each reference is spliced in as text. Referenced values are resolved
+    /// first, in whatever order the references require.
+    ///
+    /// Missing paths, cycles such as `a: ${b}` with `b: ${a}`, and a map or
+    /// list spliced into text fail with a [`BellandeError::Validation`] at
+    /// the referencing path. `${NAME:-fallback}` is left for
+    /// [`BellandeValue::expand_env_vars`].
+    pub fn resolve_references(&self) -> Result<BellandeValue, BellandeError> {
+        let mut resolved = self.clone();
+        let mut done = BTreeSet::new();
+        for path in self.reference_paths() {
+            Self::resolve_reference_at(&mut resolved, &path, &mut Vec::new(), &mut done)?;
+        }
+        Ok(resolved)
+    }
+
+    /// Checks that [`BellandeValue::resolve_references`] would succeed,
+    /// reporting every missing target, cycle and map or list spliced into
+    /// text instead of only the first. A value that fails is not reported
+    /// again for each string referring to it. Interpolation is the only kind
+    /// of reference a document can hold.
+    pub fn validate_references(&self) -> Result<(), Vec<BellandeError>> {
+        let mut resolved = self.clone();
+        let mut done = BTreeSet::new();
+        let mut errors = Vec::new();
+        for path in self.reference_paths() {
+            let mut in_progress = Vec::new();
+            if let Err(err) =
+                Self::resolve_reference_at(&mut resolved, &path, &mut in_progress, &mut done)
+            {
+                done.extend(in_progress);
+                errors.push(err);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn reference_paths(&self) -> Vec<String> {
+        self.leaves()
+            .filter(|(_, value)| value.as_str().is_some_and(|s| s.contains("${")))
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    fn resolve_reference_at(
+        tree: &mut BellandeValue,
+        path: &str,
+        in_progress: &mut Vec<String>,
+        done: &mut BTreeSet<String>,
+    ) -> Result<(), BellandeError> {
+        let error = |message: String| BellandeError::Validation {
+            path: path.to_string(),
+            message,
+        };
+        if done.contains(path) {
+            return Ok(());
+        }
+        if let Some(start) = in_progress.iter().position(|open| open == path) {
+            let mut cycle = in_progress[start..].to_vec();
+            cycle.push(path.to_string());
+            return Err(error(format!("reference cycle: {}", cycle.join(" -> "))));
+        }
+        let Some(BellandeValue::String(text)) = tree.get_path(path).cloned() else {
+            done.insert(path.to_string());
+            return Ok(());
+        };
+        in_progress.push(path.to_string());
+
+        let mut spliced = String::new();
+        let mut whole = None;
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find("${") {
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            let reference = &rest[start + 2..start + len];
+            spliced.push_str(&rest[..start]);
+            rest = &rest[start + len + 1..];
+            if reference.contains(":-") {
+                spliced.push_str(&format!("${{{}}}", reference));
+                continue;
+            }
+
+            // Resolve whatever the reference passes through or points at.
+            let segments: Vec<&str> = reference.split('.').collect();
+            for end in 1..=segments.len() {
+                Self::resolve_reference_at(tree, &segments[..end].join("."), in_progress, done)?;
+            }
+            let nested: Vec<String> = tree
+                .get_path(reference)
+                .ok_or_else(|| error(format!("`${{{}}}` refers to a missing value", reference)))?
+                .reference_paths()
+                .into_iter()
+                .map(|leaf| {
+                    if leaf.is_empty() {
+                        reference.to_string()
+                    } else {
+                        format!("{}.{}", reference, leaf)
+                    }
+                })
+                .collect();
+            for leaf in nested {
+                Self::resolve_reference_at(tree, &leaf, in_progress, done)?;
+            }
+
+            let target = tree
+                .get_path(reference)
+                .cloned()
+                .unwrap_or(BellandeValue::Null);
+            if start == 0 && rest.is_empty() && spliced.is_empty() {
+                whole = Some(target);
+                break;
+            }
+            match target {
+                BellandeValue::String(s) => spliced.push_str(&s),
+                BellandeValue::Map(_) | BellandeValue::List(_) => {
+                    return Err(error(format!(
+                        "`${{{}}}` is a {} and cannot be part of a string",
+                        reference,
+                        target.type_name()
+                    )));
+                }
+                scalar => spliced
+                    .push_str(&BellandeFormat.format_value(&scalar, &WriteOptions::default())),
+            }
+        }
+        spliced.push_str(rest);
+
+        let value = whole.unwrap_or(BellandeValue::String(spliced));
+        tree.set_path(path, value)?;
+        in_progress.pop();
+        done.insert(path.to_string());
+        Ok(())
+    }
+
+    /// Returns a deep copy with every map entry named in `keys` removed, at
+    /// any depth.
+    pub fn without_keys(&self, keys: &[&str]) -> BellandeValue {
+        match self {
+            BellandeValue::Map(map) => BellandeValue::Map(
+                map.iter()
+                    .filter(|(key, _)| !keys.contains(&key.as_str()))
+                    .map(|(key, value)| (key.clone(), value.without_keys(keys)))
+                    .collect(),
+            ),
+            BellandeValue::List(list) => {
+                BellandeValue::List(list.iter().map(|item| item.without_keys(keys)).collect())
+            }
+            scalar => scalar.clone(),
+        }
+    }
+
+    /// Returns a copy with every map key lowercased, at any depth. Two keys
+    /// of one map that differ only in case, such as `Host` and `host`, fail
+    /// with a [`BellandeError::Validation`] at the lowercased path.
+    pub fn lowercase_keys(&self) -> Result<BellandeValue, BellandeError> {
+        self.lowercase_keys_at("")
+    }
+
+    fn lowercase_keys_at(&self, path: &str) -> Result<BellandeValue, BellandeError> {
+        Ok(match self {
+            BellandeValue::Map(map) => {
+                let mut lowered: BTreeMap<String, BellandeValue> = BTreeMap::new();
+                let mut originals: BTreeMap<String, &str> = BTreeMap::new();
+                for key in map.keys() {
+                    let lower = key.to_lowercase();
+                    let child_path = Self::join_path(path, &lower);
+                    if let Some(first) = originals.insert(lower.clone(), key) {
+                        return Err(BellandeError::Validation {
+                            path: child_path,
+                            message: format!(
+                                "keys `{}` and `{}` collide when lowercased",
+                                first, key
+                            ),
+                        });
+                    }
+                    lowered.insert(lower, map[key].lowercase_keys_at(&child_path)?);
+                }
+                BellandeValue::Map(lowered)
+            }
+            BellandeValue::List(list) => BellandeValue::List(
+                list.iter()
+                    .enumerate()
+                    .map(|(i, item)| item.lowercase_keys_at(&Self::join_path(path, &i.to_string())))
+                    .collect::<Result<_, _>>()?,
+            ),
+            scalar => scalar.clone(),
+        })
+    }
+
+    /// Returns a preview copy for display. Strings longer than `max_str_len`
+    /// characters are cut and end in `…`. Lists keep their first `max_items`
+    /// items followed by a `"… N more"` string; maps keep the first
+    /// `max_items` keys in sorted order plus a `"…"` key holding `"N more"`.
+    pub fn summary(&self, max_items: usize, max_str_len: usize) -> BellandeValue {
+        match self {
+            BellandeValue::String(s) if s.chars().count() > max_str_len => {
+                let mut cut: String = s.chars().take(max_str_len).collect();
+                cut.push('…');
+                BellandeValue::String(cut)
+            }
+            BellandeValue::List(list) => {
+                let mut items: Vec<BellandeValue> = list
+                    .iter()
+                    .take(max_items)
+                    .map(|item| item.summary(max_items, max_str_len))
+                    .collect();
+                if list.len() > max_items {
+                    items.push(BellandeValue::String(format!(
+                        "… {} more",
+                        list.len() - max_items
+                    )));
+                }
+                BellandeValue::List(items)
+            }
+            BellandeValue::Map(map) => {
+                let mut entries: BTreeMap<String, BellandeValue> = map
+                    .iter()
+                    .take(max_items)
+                    .map(|(key, value)| (key.clone(), value.summary(max_items, max_str_len)))
+                    .collect();
+                if map.len() > max_items {
+                    entries.insert(
+                        "…".to_string(),
+                        BellandeValue::String(format!("{} more", map.len() - max_items)),
+                    );
+                }
+                BellandeValue::Map(entries)
+            }
+            scalar => scalar.clone(),
+        }
+    }
+
+    /// Looks up a value by an RFC 6901 JSON Pointer such as `/server/port`.
+    /// Segments index into maps by key and into lists by position; `~1` and
+    /// `~0` decode to `/` and `~`. The empty pointer refers to `self`.
+    pub fn pointer(&self, ptr: &str) -> Option<&BellandeValue> {
+        if ptr.is_empty() {
+            return Some(self);
+        }
+        let mut current = self;
+        for segment in ptr.strip_prefix('/')?.split('/') {
+            let segment = segment.replace("~1", "/").replace("~0", "~");
+            current = match current {
+                BellandeValue::Map(map) => map.get(&segment)?,
+                BellandeValue::List(list) => {
+                    if segment.len() > 1 && segment.starts_with('0') {
+                        return None;
+                    }
+                    list.get(segment.parse::<usize>().ok()?)?
+                }
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+}
+
+/// Builds a [`BellandeValue`] through chained calls instead of nested enum
+/// literals. `map()` and `list()` open a container that `end()` closes; a
+/// value inside a map needs a `key()` before it.
+///
+/// ```text
+/// let config = BellandeBuilder::new()
+///     .map()
+///     .key("server").map().key("port").int(8080).end()
+///     .key("hosts").list().string("a").string("b").end()
+///     .build()?;
+/// ```
+#[derive(Debug, Default)]
+pub struct BellandeBuilder {
+    open: Vec<(Option<String>, BellandeValue)>,
+    key: Option<String>,
+    root: Option<BellandeValue>,
+    error: Option<BellandeError>,
+}
+
+impl BellandeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names the entry that the next value or container fills.
+    pub fn key(mut self, key: &str) -> Self {
+        if self.error.is_none() && self.key.replace(key.to_string()).is_some() {
+            self.fail("a key needs a value before the next key");
+        }
+        self
+    }
+
+    pub fn map(self) -> Self {
+        self.open(BellandeValue::Map(BTreeMap::new()))
+    }
+
+    pub fn list(self) -> Self {
+        self.open(BellandeValue::List(Vec::new()))
+    }
+
+    /// Closes the innermost open `map()` or `list()`.
+    pub fn end(mut self) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        match self.open.pop() {
+            Some((key, container)) => {
+                self.key = key;
+                self.place(container);
+            }
+            None => self.fail("`end` without an open map or list"),
+        }
+        self
+    }
+
+    pub fn value(mut self, value: BellandeValue) -> Self {
+        if self.error.is_none() {
+            self.place(value);
+        }
+        self
+    }
+
+    pub fn string(self, value: &str) -> Self {
+        self.value(BellandeValue::String(value.to_string()))
+    }
+
+    pub fn int(self, value: i64) -> Self {
+        self.value(BellandeValue::Integer(value))
+    }
+
+    pub fn float(self, value: f64) -> Self {
+        self.value(BellandeValue::Float(value))
+    }
+
+    pub fn bool(self, value: bool) -> Self {
+        self.value(BellandeValue::Boolean(value))
+    }
+
+    pub fn null(self) -> Self {
+        self.value(BellandeValue::Null)
+    }
+
+    /// Closes any containers still open and returns the finished value, or
+    /// the first misuse recorded along the way.
+    pub fn build(mut self) -> Result<BellandeValue, BellandeError> {
+        if self.key.is_some() {
+            self.fail("a key needs a value before `build`");
+        }
+        while self.error.is_none() && !self.open.is_empty() {
+            self = self.end();
+        }
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(self.root.unwrap_or(BellandeValue::Null)),
+        }
+    }
+
+    fn open(mut self, container: BellandeValue) -> Self {
+        if self.error.is_none() {
+            let key = self.key.take();
+            if self.open.is_empty() && key.is_none() && self.root.is_some() {
+                self.fail("the document already has a root value");
+            } else {
+                self.open.push((key, container));
+            }
+        }
+        self
+    }
+
+    fn place(&mut self, value: BellandeValue) {
+        let problem = match (self.open.last_mut(), &self.key) {
+            (Some((_, BellandeValue::Map(map))), Some(key)) => {
+                map.insert(key.clone(), value);
+                None
+            }
+            (Some((_, BellandeValue::List(list))), None) => {
+                list.push(value);
+                None
+            }
+            (Some((_, BellandeValue::List(_))), Some(_)) => Some("a list item cannot have a key"),
+            (Some(_), _) => Some("a map value needs a key"),
+            (None, Some(_)) => Some("a key outside of any map"),
+            (None, None) if self.root.is_some() => Some("the document already has a root value"),
+            (None, None) => {
+                self.root = Some(value);
+                None
+            }
+        };
+        match problem {
+            Some(message) => self.fail(message),
+            None => self.key = None,
+        }
+    }
+
+    /// The dotted path of the slot being filled, for error messages.
+    fn path(&self) -> String {
+        let mut segments = Vec::new();
+        for (key, container) in &self.open {
+            segments.extend(key.clone());
+            if let BellandeValue::List(list) = container {
+                segments.push(list.len().to_string());
+            }
+        }
+        segments.extend(self.key.clone());
+        segments.join(".")
+    }
+
+    fn fail(&mut self, message: &str) {
+        if self.error.is_none() {
+            self.error = Some(BellandeError::Validation {
+                path: self.path(),
+                message: message.to_string(),
+            });
+        }
+    }
+}
+
+/// How [`BellandeValue::merge_with`] combines a list with an overlay list.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ListStrategy {
+    /// The overlay list replaces the base list.
+    #[default]
+    Replace,
+    /// The overlay items are appended to the base list.
+    Concat,
+    /// Items at the same index are merged; extra overlay items are appended.
+    MergeByIndex,
+    /// Map items with equal values under this key are merged; any other
+    /// overlay item is appended.
+    MergeByKey(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Expands `server.port: 8080` into `server: { port: 8080 }`. Off by
+    /// default so keys that legitimately contain dots are kept verbatim.
+    pub expand_dotted_keys: bool,
+    /// Prefixes that start a comment, either at the beginning of a line or
+    /// after whitespace following a value (`port: 80 # default`).
+    pub comment_prefixes: Vec<String>,
+    /// Refuses to load files larger than this many bytes. The size is checked
+    /// before reading and the read itself is capped, so the limit also holds
+    /// for files that grow while being read.
+    pub max_file_bytes: Option<u64>,
+    /// Rejects any line longer than this many bytes, not counting the line
+    /// terminator. When reading a file the check happens while reading, so a
+    /// huge line without a newline is never buffered in full.
+    pub max_line_bytes: Option<usize>,
+    /// Rejects documents with more than this many map entries and list
+    /// items in total, counted at every depth and inside `{ }` and `[ ]`.
+    /// Unlike the byte limits this also bounds the tree built from a small
+    /// file of many short lines.
+    pub max_nodes: Option<usize>,
+    /// What to do when a scalar has extra tokens after it, such as
+    /// `key: value extra` or `key: "quoted" extra`.
+    pub trailing_content: TrailingContent,
+    /// Keeps unquoted numbers such as `007` or `0123456789` as strings, so
+    /// identifiers, zip codes and phone numbers don't lose their leading zero.
+    pub preserve_leading_zeros: bool,
+    /// Resolves profiled keys such as `port@prod` against this profile. A
+    /// key for the active profile replaces the bare key of the same name
+    /// wherever each appears in the map; keys for other profiles are dropped.
+    /// When `None`, `@` has no special meaning and keys are kept verbatim.
+    pub profile: Option<String>,
+    /// Skips malformed lines, reporting each as a [`BellandeWarning`] whose
+    /// message starts with `skipped:`, instead of failing on the first one.
+    /// Any syntax error confined to one line is recoverable: a missing key,
+    /// a line that is neither `key: value` nor `- item`, mixing list items
+    /// with map entries, or an unclosed flow collection, whose following
+    /// lines are then read on their own. Exceeded limits, invalid UTF-8,
+    /// content after a root scalar and errors over the finished tree (from
+    /// `interpolate` or `lowercase_keys`) still fail.
+    pub lenient: bool,
+    /// Infers booleans, null and numbers from unquoted values. When off,
+    /// every scalar is read as a string unless it carries an explicit
+    /// `!int`, `!float`, `!bool` or `!null` tag, e.g. `port: !int 8080`.
+    /// Tags, `!str` included, are honored either way.
+    pub infer_types: bool,
+    /// Consulted for every unquoted scalar after tags are applied and before
+    /// the built-in inference, so domain types such as IP addresses or UUIDs
+    /// can be recognized. Returning `None` falls back to the default rules.
+    pub scalar_resolver: Option<ScalarResolver>,
+    /// Stores every map key lowercased, as with
+    /// [`BellandeValue::lowercase_keys`]. Keys that differ only in case fail
+    /// to parse instead of silently replacing one another.
+    pub lowercase_keys: bool,
+    /// Also reads `~` as null ([`NullStyle::Tilde`]), or a `key:` with no
+    /// nested block as null rather than an empty list ([`NullStyle::Empty`]).
+    pub null_style: NullStyle,
+    /// Replaces `${path.to.key}` references with other values of the same
+    /// document, as with [`BellandeValue::resolve_references`]. Applied
+    /// after profile resolution.
+    pub interpolate: bool,
+    /// Separates keys from values, `:` by default. With `=`, files such as
+    /// `key = value` can be read. The first separator outside double quotes
+    /// ends the key; flow collections keep using `:`.
+    pub kv_separator: char,
+    /// Keeps each block that starts this many levels below the top
+    /// unparsed, as a [`BellandeValue::Raw`] of its dedented source, so a
+    /// large document can be read for its outline and its sections parsed
+    /// on demand with [`BellandeFormat::materialize`]. With `Some(1)` only
+    /// the top-level keys and their inline values are read.
+    pub max_parse_depth_materialized: Option<usize>,
+    /// Called with the number of lines read so far every
+    /// [`ProgressHook::INTERVAL`] lines, and once more with the total when
+    /// the document is done, so a tool reading a huge file can show progress.
+    pub progress: Option<ProgressHook>,
+    /// How many columns apart tab stops are when measuring indentation, 8
+    /// by default. A tab advances to the next stop, so `\t` and eight
+    /// spaces nest alike. Each line whose indentation mixes tabs and spaces
+    /// gets a [`BellandeWarning`].
+    pub tab_width: usize,
+}
+
+/// A path where two trees differ, with the value on each side, if any.
+type Change<'a> = (String, Option<&'a BellandeValue>, Option<&'a BellandeValue>);
+
+/// A user-supplied scalar rule for [`ParseOptions::scalar_resolver`].
+#[derive(Clone)]
+pub struct ScalarResolver(Arc<ResolveFn>);
+
+type ResolveFn = dyn Fn(&str) -> Option<BellandeValue> + Send + Sync;
+
+impl ScalarResolver {
+    pub fn new(resolve: impl Fn(&str) -> Option<BellandeValue> + Send + Sync + 'static) -> Self {
+        ScalarResolver(Arc::new(resolve))
+    }
+}
+
+impl fmt::Debug for ScalarResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ScalarResolver(..)")
+    }
+}
+
+/// A user-supplied callback for [`ParseOptions::progress`].
+#[derive(Clone)]
+pub struct ProgressHook(Arc<ProgressFn>);
+
+type ProgressFn = dyn Fn(usize) + Send + Sync;
+
+impl ProgressHook {
+    /// How many lines are read between two calls.
+    pub const INTERVAL: usize = 4096;
+
+    pub fn new(progress: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        ProgressHook(Arc::new(progress))
+    }
+
+    fn report(&self, lines: usize) {
+        (self.0)(lines)
+    }
+}
+
+impl fmt::Debug for ProgressHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressHook(..)")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingContent {
+    /// Keep the whole text as the value, e.g. `name: John Doe`.
+    #[default]
+    Allow,
+    /// Keep the value but report a [`BellandeWarning`].
+    Warn,
+    /// Fail with a [`BellandeError::Parse`].
+    Reject,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            expand_dotted_keys: false,
+            comment_prefixes: vec!["#".to_string()],
+            max_file_bytes: None,
+            max_line_bytes: None,
+            max_nodes: None,
+            trailing_content: TrailingContent::Allow,
+            preserve_leading_zeros: false,
+            profile: None,
+            lenient: false,
+            infer_types: true,
+            scalar_resolver: None,
+            lowercase_keys: false,
+            null_style: NullStyle::Word,
+            interpolate: false,
+            kv_separator: ':',
+            max_parse_depth_materialized: None,
+            progress: None,
+            tab_width: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Writes every non-ASCII character in a string as a `\u{...}` escape.
+    /// Control characters are always escaped.
+    pub escape_non_ascii: bool,
+    /// Starts the output with a `---` document marker line. The parser skips
+    /// such a marker when it comes before any content.
+    pub document_start: bool,
+    /// Starts the output with this `#!` interpreter line, e.g.
+    /// `#!/usr/bin/env bellande-run`. The parser skips a first line starting
+    /// with `#!` whatever the comment prefixes are.
+    pub shebang: Option<String>,
+    /// Starts the output with this comment block, written verbatim after
+    /// the `#!` line and before the `---` marker, e.g. a license header
+    /// taken from the previous file with [`BellandeFormat::header_comment`].
+    pub header: Option<String>,
+    /// Writes floats outside these magnitudes in scientific notation, such as
+    /// `1e9` instead of `1000000000.0`. Integers are never affected.
+    pub scientific_notation: Option<ScientificNotation>,
+    /// Pads the keys of each map so their colons line up. Keys longer than
+    /// [`ALIGN_COLONS_MAX_WIDTH`] are left unpadded and do not widen the
+    /// column for their siblings.
+    pub align_colons: bool,
+    /// Writes every map key lowercased, as with
+    /// [`BellandeValue::lowercase_keys`]. Keys that differ only in case make
+    /// the write fail rather than produce duplicate keys.
+    pub lowercase_keys: bool,
+    /// How null values are written. Pair it with the same
+    /// [`ParseOptions::null_style`] to read the output back.
+    pub null_style: NullStyle,
+    /// Writes `key = value` style lines with this separator instead of
+    /// `key: value`. Strings containing it are quoted. Read the output back
+    /// with the same [`ParseOptions::kv_separator`].
+    pub kv_separator: Option<char>,
+    /// Where the dashes of a list under a map key go.
+    pub list_indent: ListIndent,
+    /// Whether strings are quoted only when they must be or always.
+    pub quote_style: QuotePolicy,
+    /// Writes every scalar with its type tag, e.g. `!int 8080`, `!str demo`
+    /// or `!null`, so the output reads back as the same tree even with
+    /// [`ParseOptions::infer_types`] off. Non-finite floats are written as
+    /// `!float NaN`, `!float inf` and `!float -inf`.
+    pub emit_tags: bool,
+    /// Follows each scalar with a comment naming the type it reads back as
+    /// (see [`BellandeValue::type_name`]), e.g. `port: 8080  # integer`, to
+    /// show how values are inferred. Comments are ignored when parsing, so
+    /// the output reads back as the same tree. A null written as nothing
+    /// under [`NullStyle::Empty`] gets no comment.
+    pub type_comments: bool,
+}
+
+/// When [`WriteOptions::quote_style`] quotes strings. Map keys are never
+/// quoted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuotePolicy {
+    /// Only strings that would otherwise read back as something else, such
+    /// as `"8080"`, `"true"` or text with `:` or `#`.
+    #[default]
+    Minimal,
+    /// Every string, for consumers that expect all text in quotes.
+    Always,
+}
+
+/// How [`WriteOptions::list_indent`] places list items under a key. Both
+/// styles parse back to the same value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListIndent {
+    /// One level deeper than the key: `key:\n  - a`.
+    #[default]
+    Indented,
+    /// In the key's own column: `key:\n- a`.
+    Aligned,
+}
+
+/// Receives a value piece by piece from [`BellandeFormat::write_with`], so
+/// other outputs such as XML or HTML can be produced directly instead of by
+/// post-processing Bellande text. The block style written by
+/// `write_bellande` is itself produced through this trait.
+pub trait BellandeWriter {
+    /// A string, number, boolean, null or [`BellandeValue::Raw`] block,
+    /// either the whole value or the value of the key or item announced just
+    /// before.
+    fn scalar(&mut self, value: &BellandeValue);
+    /// Opens a map whose entries follow, with their keys in that order.
+    fn begin_map(&mut self, keys: &[&str]);
+    /// Names the map entry whose value comes next.
+    fn key(&mut self, key: &str);
+    fn end_map(&mut self);
+    /// Opens a list of `len` items.
+    fn begin_list(&mut self, len: usize);
+    /// Starts the list item at `index`, whose value comes next.
+    fn item(&mut self, index: usize) {
+        let _ = index;
+    }
+    fn end_list(&mut self);
+}
+
+/// The widest key that [`WriteOptions::align_colons`] aligns to, in characters.
+pub const ALIGN_COLONS_MAX_WIDTH: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScientificNotation {
+    /// Floats whose magnitude is at least this, e.g. `1e6`.
+    pub at_least: f64,
+    /// Non-zero floats whose magnitude is below this, e.g. `1e-4`.
+    pub below: f64,
+}
+
+/// How null is spelled. The word `null` is always read as null; the other
+/// styles add their own spelling when set in [`ParseOptions::null_style`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NullStyle {
+    /// `key: null`
+    #[default]
+    Word,
+    /// `key: ~`, as in YAML.
+    Tilde,
+    /// `key:` with nothing after it or nested below it, and a bare `-` item.
+    Empty,
+}
+
+/// Validation rules keyed by dotted path (see [`BellandeValue::get_path`]).
+#[derive(Debug, Clone, Default)]
+pub struct BellandeSchema {
+    pub fields: BTreeMap<String, FieldSchema>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FieldSchema {
+    pub required: bool,
+    /// Restricts the field to these strings, e.g. `debug`, `info`, `warn`
+    /// and `error` for a log level.
+    pub allowed_values: Option<Vec<String>>,
+}
+
+impl BellandeSchema {
+    /// Checks `value` against every field and returns all violations, ordered
+    /// by path.
+    pub fn validate(&self, value: &BellandeValue) -> Result<(), Vec<BellandeError>> {
+        let mut errors = Vec::new();
+        for (path, field) in &self.fields {
+            let error = |message: String| BellandeError::Validation {
+                path: path.clone(),
+                message,
+            };
+            let found = match value.get_path(path) {
+                Some(found) => found,
+                None => {
+                    if field.required {
+                        errors.push(error("required field is missing".to_string()));
+                    }
+                    continue;
+                }
+            };
+            if let Some(allowed) = &field.allowed_values {
+                if !found
+                    .as_str()
+                    .is_some_and(|s| allowed.iter().any(|a| a == s))
+                {
+                    errors.push(error(format!(
+                        "expected one of {}, found {}",
+                        allowed.join(", "),
+                        BellandeFormat.format_value_or_kind(found)
+                    )));
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+struct FlowParser<'a> {
+    format: &'a BellandeFormat,
+    options: &'a ParseOptions,
+    text: &'a str,
+    pos: usize,
+    /// Map entries and list items parsed so far.
+    nodes: usize,
+}
+
+type FlowResult<T> = Result<T, (usize, String)>;
+
+impl FlowParser<'_> {
+    fn parse_document(&mut self) -> FlowResult<BellandeValue> {
+        let value = self.parse_value()?;
+        self.skip_space(true);
+        match self.peek() {
+            None => Ok(value),
+            Some(c) => Err((self.pos, format!("unexpected `{}` after value", c))),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn skip_space(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            if c == '\n' && !newlines {
+                break;
+            } else if c.is_whitespace() {
+                self.pos += c.len_utf8();
+            } else if self.format.is_comment(&self.text[self.pos..], self.options) {
+                let rest = &self.text[self.pos..];
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> FlowResult<BellandeValue> {
+        self.skip_space(true);
+        match self.peek() {
+            Some('{') => self.parse_map(),
+            Some('[') => self.parse_list(),
+            Some('"') => {
+                let start = self.pos;
+                let end = self
+                    .format
+                    .closing_quote(&self.text[start + 1..])
+                    .ok_or((start, "unterminated string".to_string()))?;
+                self.pos = start + end + 2;
+                self.format
+                    .resolve_scalar(&self.text[start..self.pos], self.options)
+                    .map_err(|message| (start, message))
+            }
+            Some(c) if !matches!(c, ',' | '}' | ']') => {
+                let rest = &self.text[self.pos..];
+                let mut end = rest.len();
+                let mut after_whitespace = false;
+                for (offset, c) in rest.char_indices() {
+                    if matches!(c, ',' | '}' | ']' | '\n')
+                        || (after_whitespace
+                            && self.format.is_comment(&rest[offset..], self.options))
+                    {
+                        end = offset;
+                        break;
+                    }
+                    after_whitespace = c.is_whitespace();
+                }
+                let start = self.pos;
+                self.pos += end;
+                self.format
+                    .resolve_scalar(rest[..end].trim(), self.options)
+                    .map_err(|message| (start, message))
+            }
+            _ => Err((self.pos, "expected a value".to_string())),
+        }
+    }
+
+    fn parse_map(&mut self) -> FlowResult<BellandeValue> {
+        let open = self.pos;
+        self.pos += 1;
+        let mut map = BTreeMap::new();
+        loop {
+            self.skip_space(true);
+            match self.peek() {
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(BellandeValue::Map(map));
+                }
+                None => return Err((open, "unclosed `{`".to_string())),
+                Some(_) => {}
+            }
+            let key = self.parse_key()?;
+            let value = self.parse_value()?;
+            map.insert(key, value);
+            self.nodes += 1;
+            self.end_of_entry('}')?;
+        }
+    }
+
+    fn parse_list(&mut self) -> FlowResult<BellandeValue> {
+        let open = self.pos;
+        self.pos += 1;
+        let mut list = Vec::new();
+        loop {
+            self.skip_space(true);
+            match self.peek() {
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(BellandeValue::List(list));
+                }
+                None => return Err((open, "unclosed `[`".to_string())),
+                Some(_) => {}
+            }
+            list.push(self.parse_value()?);
+            self.nodes += 1;
+            self.end_of_entry(']')?;
+        }
+    }
+
+    fn parse_key(&mut self) -> FlowResult<String> {
+        let rest = &self.text[self.pos..];
+        let end = rest.find([':', ',', '}', ']', '\n']).unwrap_or(rest.len());
+        let key = rest[..end].trim();
+        if !rest[end..].starts_with(':') {
+            return Err((self.pos, "expected `key: value`".to_string()));
+        }
+        if key.is_empty() {
+            return Err((self.pos, "missing key before `:`".to_string()));
+        }
+        self.pos += end + 1;
+        Ok(key.to_string())
+    }
+
+    /// Consumes the separator after an entry: a `,`, a line break, or a
+    /// line break followed by a `,` that starts the next line.
+    fn end_of_entry(&mut self, close: char) -> FlowResult<()> {
+        self.skip_space(false);
+        match self.peek() {
+            Some(',') => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some('\n') => {
+                self.skip_space(true);
+                if self.peek() == Some(',') {
+                    self.pos += 1;
+                }
+                Ok(())
+            }
+            Some(c) if c == close => Ok(()),
+            Some(c) => Err((
+                self.pos,
+                format!("expected `,` or `{}`, found `{}`", close, c),
+            )),
+            None => Err((self.pos, format!("expected `{}`", close))),
+        }
+    }
+}
+
+/// A concrete syntax tree of a Bellande source file.
+///
+/// Unlike [`BellandeValue`], the document keeps every comment, blank line,
+/// indentation and quoting choice of the original text, so it can be written
+/// back unchanged with [`BellandeDocument::to_source`] or restyled with
+/// [`BellandeDocument::format`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BellandeDocument {
+    pub nodes: Vec<CstNode>,
+    pub trailing: Vec<Trivia>,
+    pub final_newline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstNode {
+    pub kind: CstNodeKind,
+    pub indent: usize,
+    pub raw: String,
+    pub leading: Vec<Trivia>,
+    pub children: Vec<CstNode>,
+    /// Comments after the last child that are still indented inside this
+    /// node, such as a note below the last field of a list item.
+    pub trailing: Vec<Trivia>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CstNodeKind {
+    Entry {
+        key: String,
+        value: Option<CstScalar>,
+    },
+    Item {
+        value: Option<CstScalar>,
+    },
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstScalar {
+    pub raw: String,
+    pub quote: QuoteStyle,
+}
+
+/// How a scalar was written in the source. Only double quotes delimit a
+/// string; text in single quotes is read literally, quotes included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteStyle {
+    Plain,
+    Single,
+    Double,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub raw: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriviaKind {
+    Blank,
+    Comment(String),
+    /// A `#!` interpreter line, only recognized as the very first line.
+    Shebang(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BellandeFormat;
+
+struct ParseState<'a> {
+    options: &'a ParseOptions,
+    lines: &'a [&'a str],
+    next_line: usize,
+    /// The document value, holding everything outside the open blocks.
+    root: BellandeValue,
+    /// The open blocks below the root, outermost first.
+    stack: Vec<Block>,
+    /// The indentation of the lines seen so far at each depth of `stack`.
+    child_indents: Vec<Option<usize>>,
+    /// Map entries and list items added so far, for `max_nodes`.
+    nodes: usize,
+    warnings: Vec<BellandeWarning>,
+    line: usize,
+    column: usize,
+}
+
+/// A block opened by `key:` or a list item. Its value is taken out of the
+/// parent while the block is open, so each line is inserted directly instead
+/// of walking down from the root, and is put back when the block closes.
+struct Block {
+    /// The indentation of the line that opened the block.
+    indent: usize,
+    /// The key in the parent map, or the index in the parent list.
+    key: String,
+    /// `None` when the parent cannot hold `key`, such as a scalar or a list
+    /// given a non-index key; lines then go to the nearest enclosing value.
+    value: Option<BellandeValue>,
+}
+
+impl ParseState<'_> {
+    /// The value that the current line adds to.
+    fn target(&mut self) -> &mut BellandeValue {
+        self.stack
+            .iter_mut()
+            .rev()
+            .find_map(|block| block.value.as_mut())
+            .unwrap_or(&mut self.root)
+    }
+
+    fn error(&self, message: impl Into<String>) -> BellandeError {
+        BellandeError::parse(self.line, self.column, message)
+    }
+
+    fn count_nodes(&mut self, count: usize) -> Result<(), BellandeError> {
+        self.nodes += count;
+        match self.options.max_nodes {
+            Some(limit) if self.nodes > limit => Err(BellandeError::LimitExceeded(format!(
+                "more than {} nodes by line {}",
+                limit, self.line
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn warn(&mut self, kind: WarningKind, message: impl Into<String>) {
+        self.warnings.push(BellandeWarning {
+            line: self.line,
+            column: self.column,
+            kind,
+            message: message.into(),
+        });
+    }
+}
+
+impl BellandeFormat {
+    #[cfg(feature = "std")]
+    pub fn parse_bellande<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+    ) -> Result<BellandeValue, BellandeError> {
+        self.parse_bellande_with_options(file_path, &ParseOptions::default())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn parse_bellande_with_options<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        options: &ParseOptions,
+    ) -> Result<BellandeValue, BellandeError> {
+        let content = if options.max_file_bytes.is_some() || options.max_line_bytes.is_some() {
+            self.read_limited(file_path.as_ref(), options)?
+        } else {
+            fs::read(file_path)?
+        };
+        self.parse_bellande_bytes_with_options(&content, options)
+    }
+
+    /// Parses a file with [`ParseOptions::lenient`] set, returning the value
+    /// together with every warning: skipped lines, duplicate keys, uneven
+    /// indentation and number-like strings. Only I/O and encoding problems
+    /// fail.
+    #[cfg(feature = "std")]
+    pub fn parse_bellande_lenient<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+    ) -> Result<(BellandeValue, Vec<BellandeWarning>), BellandeError> {
+        let options = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+        let content = fs::read(file_path)?;
+        self.parse_bellande_str_with_warnings(self.decode_utf8(&content)?, &options)
+    }
+
+    /// Reads a file while enforcing `max_file_bytes` and `max_line_bytes`,
+    /// stopping as soon as either limit is passed instead of buffering the
+    /// whole input first.
+    #[cfg(feature = "std")]
+    fn read_limited(
+        &self,
+        file_path: &Path,
+        options: &ParseOptions,
+    ) -> Result<Vec<u8>, BellandeError> {
+        let file_limit = options.max_file_bytes.unwrap_or(u64::MAX);
+        let too_large = || {
+            BellandeError::LimitExceeded(format!(
+                "{} is larger than {} bytes",
+                file_path.display(),
+                file_limit
+            ))
+        };
+        let file = fs::File::open(file_path)?;
+        if file.metadata()?.len() > file_limit {
+            return Err(too_large());
+        }
+        let mut reader = std::io::BufReader::new(file.take(file_limit.saturating_add(1)));
+        let mut content = Vec::new();
+        match options.max_line_bytes {
+            Some(line_limit) => {
+                for line_number in 1.. {
+                    let start = content.len();
+                    // Room for the line, a `\r\n` terminator and one byte more.
+                    let read = (&mut reader)
+                        .take((line_limit as u64).saturating_add(3))
+                        .read_until(b'\n', &mut content)?;
+                    if read == 0 {
+                        break;
+                    }
+                    let line = &content[start..];
+                    let line = line.strip_suffix(b"\n").unwrap_or(line);
+                    let line = line.strip_suffix(b"\r").unwrap_or(line);
+                    if line.len() > line_limit {
+                        return Err(self.line_too_long(line_number, line_limit));
+                    }
+                }
+            }
+            None => {
+                reader.read_to_end(&mut content)?;
+            }
+        }
+        if content.len() as u64 > file_limit {
+            return Err(too_large());
+        }
+        Ok(content)
+    }
+
+    /// An upper bound on the number of lines, used to size buffers up front.
+    fn line_count(&self, content: &str) -> usize {
+        content.bytes().filter(|&byte| byte == b'\n').count() + 1
+    }
+
+    fn line_too_long(&self, line_number: usize, limit: usize) -> BellandeError {
+        BellandeError::LimitExceeded(format!(
+            "line {} is longer than {} bytes",
+            line_number, limit
+        ))
+    }
+
+    pub fn parse_bellande_bytes(&self, content: &[u8]) -> Result<BellandeValue, BellandeError> {
+        self.parse_bellande_bytes_with_options(content, &ParseOptions::default())
+    }
+
+    /// Parses raw bytes, failing with [`BellandeError::InvalidUtf8`] if they
+    /// are not valid UTF-8 (for instance a Latin-1 file).
+    pub fn parse_bellande_bytes_with_options(
+        &self,
+        content: &[u8],
+        options: &ParseOptions,
+    ) -> Result<BellandeValue, BellandeError> {
+        let content = self.decode_utf8(content)?;
+        self.parse_bellande_str_with_options(content, options)
+    }
+
+    fn decode_utf8<'a>(&self, content: &'a [u8]) -> Result<&'a str, BellandeError> {
+        core::str::from_utf8(content).map_err(|err| {
+            let offset = err.valid_up_to();
+            let end = offset + err.error_len().unwrap_or(content.len() - offset);
+            let hex = |bytes: &[u8]| {
+                bytes
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            let before = hex(&content[offset.saturating_sub(8)..offset]);
+            let after = hex(&content[end..content.len().min(end + 8)]);
+            let context = [before, format!("[{}]", hex(&content[offset..end])), after]
+                .into_iter()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            BellandeError::InvalidUtf8 { offset, context }
+        })
+    }
+
+    /// Parses every [`BellandeValue::Raw`] block in `value` in place, for
+    /// documents read with [`ParseOptions::max_parse_depth_materialized`].
+    /// Blocks are read with `options`, which may leave their own deeper
+    /// blocks raw again, counting levels from the block itself. Line numbers
+    /// in errors count from the start of the block.
+    pub fn materialize(
+        &self,
+        value: &mut BellandeValue,
+        options: &ParseOptions,
+    ) -> Result<(), BellandeError> {
+        match value {
+            BellandeValue::Raw(text) => {
+                *value = self.parse_bellande_str_with_options(text, options)?;
+            }
+            BellandeValue::Map(map) => {
+                for child in map.values_mut() {
+                    self.materialize(child, options)?;
+                }
+            }
+            BellandeValue::List(list) => {
+                for child in list {
+                    self.materialize(child, options)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Types a single value the way the parser types `key: value`, e.g.
+    /// `"42"` becomes an integer and `"\"42\""` the string `42`. Surrounding
+    /// whitespace is ignored; comments and flow collections are not parsed.
+    pub fn parse_scalar(&self, s: &str) -> BellandeValue {
+        self.parse_value(s.trim())
+    }
+
+    pub fn parse_bellande_str(&self, content: &str) -> Result<BellandeValue, BellandeError> {
+        self.parse_bellande_str_with_options(content, &ParseOptions::default())
+    }
+
+    pub fn parse_bellande_str_with_options(
+        &self,
+        content: &str,
+        options: &ParseOptions,
+    ) -> Result<BellandeValue, BellandeError> {
+        self.parse_bellande_str_with_warnings(content, options)
+            .map(|(value, _)| value)
+    }
+
+    /// Like [`BellandeFormat::parse_bellande_str_with_options`], but also
+    /// returns the non-fatal problems noticed along the way.
+    pub fn parse_bellande_str_with_warnings(
+        &self,
+        content: &str,
+        options: &ParseOptions,
+    ) -> Result<(BellandeValue, Vec<BellandeWarning>), BellandeError> {
+        let mut lines = Vec::with_capacity(self.line_count(content));
+        lines.extend(content.lines());
+        if let Some(limit) = options.max_line_bytes {
+            if let Some(index) = lines.iter().position(|line| line.len() > limit) {
+                return Err(self.line_too_long(index + 1, limit));
+            }
+        }
+        self.parse_lines(&lines, options)
+    }
+
+    /// Loads an application config in one call: parses `file_path`, expands
+    /// environment references in both the file and `defaults` (see
+    /// [`BellandeValue::expand_env_vars`]) and fills anything the file leaves
+    /// out from the defaults. Values from the file take precedence over the
+    /// env-expanded defaults.
+    #[cfg(feature = "std")]
+    pub fn load_config<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        defaults: &BellandeValue,
+    ) -> Result<BellandeValue, BellandeError> {
+        let file = self.parse_bellande(file_path)?.expand_env_vars();
+        Ok(file.with_defaults(&defaults.expand_env_vars()))
+    }
+
+    /// Re-emits `content` in the canonical style, keeping every comment and
+    /// value; see [`BellandeDocument::format_with_options`]. Fails if
+    /// `content` does not parse, so a formatter never rewrites a broken file.
+    pub fn reformat(content: &str, options: &WriteOptions) -> Result<String, BellandeError> {
+        BellandeFormat.parse_bellande_str(content)?;
+        Ok(BellandeFormat
+            .parse_document(content)
+            .format_with_options(options))
+    }
+
+    /// Rewrites `content` so every nesting level is indented by `width`
+    /// spaces (at least one), keeping comments and values as written. Mixed
+    /// widths are fine as long as siblings line up with each other; a sibling
+    /// at a different indentation is ambiguous and reported as a
+    /// [`BellandeError::Parse`] on its line. The fields of a map inside a
+    /// list item stay aligned after its `- `.
+    pub fn normalize_indentation(content: &str, width: usize) -> Result<String, BellandeError> {
+        let format = BellandeFormat;
+        let (_, warnings) =
+            format.parse_bellande_str_with_warnings(content, &ParseOptions::default())?;
+        if let Some(warning) = warnings
+            .iter()
+            .find(|warning| warning.kind == WarningKind::IndentationMismatch)
+        {
+            return Err(BellandeError::parse(
+                warning.line,
+                warning.column,
+                warning.message.clone(),
+            ));
+        }
+        Ok(format
+            .parse_document(content)
+            .format_inner(None, width.max(1)))
+    }
+
+    /// Parses `content`, writes it back with the default options and parses
+    /// the result again, failing unless both parses agree. A difference is
+    /// reported as a [`BellandeError::Validation`] at the first path that
+    /// changed, naming the value before and after and how many others changed.
+    pub fn verify_roundtrip(content: &str) -> Result<(), BellandeError> {
+        let format = BellandeFormat;
+        let parsed = format.parse_bellande_str(content)?;
+        let written = format.to_bellande_string(&parsed, 0, &WriteOptions::default());
+        let reparsed =
+            format
+                .parse_bellande_str(&written)
+                .map_err(|err| BellandeError::Validation {
+                    path: String::new(),
+                    message: format!("the written document does not parse: {}", err),
+                })?;
+        if reparsed == parsed {
+            return Ok(());
+        }
+
+        let mut changed = Vec::new();
+        BellandeValue::collect_changes(Some(&parsed), Some(&reparsed), String::new(), &mut changed);
+        changed.sort_by(|a, b| a.0.cmp(&b.0));
+        let Some((path, before, after)) = changed.first() else {
+            return Err(BellandeError::Validation {
+                path: String::new(),
+                message: "the document was read back differently".to_string(),
+            });
+        };
+        let show = |value: Option<&BellandeValue>| match value {
+            Some(value) => format.format_value_or_kind(value),
+            None => "nothing".to_string(),
+        };
+        let path = path.clone();
+        let mut message = format!("{} was read back as {}", show(*before), show(*after));
+        match changed.len() - 1 {
+            0 => {}
+            1 => message.push_str(" (and 1 more change)"),
+            more => message.push_str(&format!(" (and {} more changes)", more)),
+        }
+        Err(BellandeError::Validation { path, message })
+    }
+
+    /// A cheap guess at whether `content` is a Bellande document, for loaders
+    /// that accept several formats. Only the first few non-comment lines are
+    /// inspected; each must be a `key: value`, `key:` or `- item` line, and
+    /// JSON-looking input (starting with `{` or `[`) is rejected.
+    pub fn looks_like_bellande(content: &str) -> bool {
+        let mut lines = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .take(8)
+            .peekable();
+        if lines
+            .peek()
+            .is_none_or(|first| first.starts_with(['{', '[', '<']))
+        {
+            return false;
+        }
+        lines.all(|line| {
+            line == "-"
+                || line.starts_with("- ")
+                || line.starts_with(['}', ']'])
+                || line.split_once(':').is_some_and(|(key, _)| {
+                    let key = key.trim();
+                    !key.is_empty() && !key.starts_with('"') && !key.contains(' ')
+                })
+        })
+    }
+
+    /// Returns the comment block at the top of `content`, such as a license
+    /// header: the `#` lines before the first blank or other line, after an
+    /// optional `#!` line. Pass it to [`WriteOptions::header`] to keep it
+    /// when the file is written again.
+    pub fn header_comment(content: &str) -> Option<&str> {
+        let mut rest = content;
+        if rest.starts_with("#!") {
+            rest = rest.split_once('\n').map_or("", |(_, after)| after);
+        }
+        let mut end = 0;
+        for line in rest.split_inclusive('\n') {
+            if !line.trim_start().starts_with('#') {
+                break;
+            }
+            end += line.len();
+        }
+        let header = rest[..end].trim_end_matches(['\n', '\r']);
+        (!header.is_empty()).then_some(header)
+    }
+
+    /// Returns the most common indentation step between a line and the less
+    /// indented line that opens its block, or `None` if nothing is nested.
+    pub fn detect_indent_width(content: &str) -> Option<usize> {
+        let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut previous = 0;
+        for line in content.lines() {
+            let stripped = line.trim();
+            if stripped.is_empty() || stripped.starts_with('#') {
+                continue;
+            }
+            let indent = line.len() - line.trim_start().len();
+            if indent > previous {
+                *counts.entry(indent - previous).or_default() += 1;
+            }
+            previous = indent;
+        }
+        counts
+            .into_iter()
+            .max_by(|(width_a, count_a), (width_b, count_b)| {
+                count_a.cmp(count_b).then(width_b.cmp(width_a))
+            })
+            .map(|(width, _)| width)
+    }
+
+    /// Parses every `.bellande` file directly inside `dir` and merges them into
+    /// a single tree.
+    ///
+    /// Files are processed in ascending order of their file names, so `10-a`
+    /// sorts before `20-b`; subdirectories and other extensions are ignored.
+    /// Each file is merged over the result of the previous ones with
+    /// [`BellandeValue::merge`]: maps are combined recursively and, on any
+    /// other conflict, the value from the later file wins.
+    #[cfg(feature = "std")]
+    pub fn parse_bellande_dir<P: AsRef<Path>>(
+        &self,
+        dir: P,
+    ) -> Result<BellandeValue, BellandeError> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "bellande") {
+                paths.push(path);
+            }
+        }
+        paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+        let mut merged = BellandeValue::Map(BTreeMap::new());
+        for path in paths {
+            merged.merge(self.parse_bellande(&path)?);
+        }
+        Ok(merged)
+    }
+
+    pub fn parse_document(&self, content: &str) -> BellandeDocument {
+        let mut pending = Vec::new();
+        let mut flat = Vec::with_capacity(self.line_count(content));
+        let tab_width = ParseOptions::default().tab_width;
+
+        let mut lines = content.lines().enumerate();
+        while let Some((index, line)) = lines.next() {
+            let stripped = line.trim();
+            let mut raw = line.to_string();
+            if index == 0 && line.starts_with("#!") {
+                pending.push(Trivia {
+                    kind: TriviaKind::Shebang(line.to_string()),
+                    raw,
+                });
+                continue;
+            }
+            if stripped.is_empty() {
+                pending.push(Trivia {
+                    kind: TriviaKind::Blank,
+                    raw,
+                });
+                continue;
+            }
+            if let Some(comment) = stripped.strip_prefix('#') {
+                pending.push(Trivia {
+                    kind: TriviaKind::Comment(comment.to_string()),
+                    raw,
+                });
+                continue;
+            }
+
+            let mut kind = if stripped == "---" {
+                CstNodeKind::Text(stripped.to_string())
+            } else if self.is_item_line(stripped) {
+                CstNodeKind::Item {
+                    value: self.cst_scalar(&stripped[1..]),
+                }
+            } else if let Some(colon_pos) = stripped.find(':') {
+                CstNodeKind::Entry {
+                    key: stripped[..colon_pos].trim().to_string(),
+                    value: self.cst_scalar(&stripped[colon_pos + 1..]),
+                }
+            } else if let Some(value) = stripped.strip_prefix('-') {
+                CstNodeKind::Item {
+                    value: self.cst_scalar(value),
+                }
+            } else {
+                CstNodeKind::Text(stripped.to_string())
+            };
+            // A flow collection left open continues over the following lines,
+            // whose indentation carries no meaning, so they stay in its scalar.
+            if let CstNodeKind::Entry {
+                value: Some(value), ..
+            }
+            | CstNodeKind::Item { value: Some(value) } = &mut kind
+            {
+                let options = ParseOptions::default();
+                while value.raw.starts_with(['{', '[']) && self.flow_depth(&value.raw, &options) > 0
+                {
+                    let Some((_, next)) = lines.next() else {
+                        break;
+                    };
+                    for text in [&mut value.raw, &mut raw] {
+                        text.push('\n');
+                        text.push_str(next);
+                    }
+                }
+            }
+
+            let margin = &line[..line.len() - line.trim_start().len()];
+            let indent = Self::columns(margin, tab_width);
+            Self::attach_trailing_comments(&mut flat, &mut pending, indent);
+            flat.push(CstNode {
+                kind,
+                indent,
+                raw,
+                leading: core::mem::take(&mut pending),
+                children: Vec::new(),
+                trailing: Vec::new(),
+            });
+        }
+        Self::attach_trailing_comments(&mut flat, &mut pending, 0);
+
+        let mut nodes = flat.into_iter().peekable();
+        BellandeDocument {
+            nodes: Self::nest_cst_nodes(&mut nodes, None),
+            trailing: pending,
+            final_newline: content.ends_with('\n'),
+        }
+    }
+
+    /// Comments indented deeper than the line that follows them close the
+    /// block they sit in, e.g. a note after the last field of a `- item`,
+    /// so they move from that line's `leading` to the block's `trailing`.
+    fn attach_trailing_comments(
+        flat: &mut [CstNode],
+        pending: &mut Vec<Trivia>,
+        next_indent: usize,
+    ) {
+        let indent_of = |trivia: &Trivia| trivia.raw.len() - trivia.raw.trim_start().len();
+        let mut count = 0;
+        for (i, trivia) in pending.iter().enumerate() {
+            match trivia.kind {
+                TriviaKind::Comment(_) if indent_of(trivia) > next_indent => count = i + 1,
+                TriviaKind::Blank => {}
+                _ => break,
+            }
+        }
+        let Some(first) = pending[..count]
+            .iter()
+            .find(|trivia| matches!(trivia.kind, TriviaKind::Comment(_)))
+        else {
+            return;
+        };
+        let comment_indent = indent_of(first);
+        let owner = flat
+            .iter_mut()
+            .rev()
+            .find(|node| node.indent < comment_indent)
+            .filter(|node| node.indent >= next_indent);
+        if let Some(owner) = owner {
+            owner.trailing.extend(pending.drain(..count));
+        }
+    }
+
+    fn cst_scalar(&self, value: &str) -> Option<CstScalar> {
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        let scalar = self.strip_inline_comment(value, &ParseOptions::default());
+        let quote = if scalar.len() >= 2 && scalar.starts_with('"') && scalar.ends_with('"') {
+            QuoteStyle::Double
+        } else if scalar.len() >= 2 && scalar.starts_with('\'') && scalar.ends_with('\'') {
+            QuoteStyle::Single
+        } else {
+            QuoteStyle::Plain
+        };
+        Some(CstScalar {
+            raw: value.to_string(),
+            quote,
+        })
+    }
+
+    fn nest_cst_nodes(
+        nodes: &mut core::iter::Peekable<alloc::vec::IntoIter<CstNode>>,
+        parent_indent: Option<usize>,
+    ) -> Vec<CstNode> {
+        let mut siblings = Vec::new();
+        while let Some(next) = nodes.peek() {
+            if parent_indent.is_some_and(|parent| next.indent <= parent) {
+                break;
+            }
+            let mut node = nodes.next().unwrap();
+            node.children = Self::nest_cst_nodes(nodes, Some(node.indent));
+            siblings.push(node);
+        }
+        siblings
+    }
+
+    /// Renders `err` as a diagnostic pointing into `source`, in the style of
+    /// compiler errors:
+    ///
+    /// ```text
+    /// error: expected `key: value` or `- item`
+    ///  --> line 3, column 5
+    ///   |
+    /// 3 |     oops
+    ///   |     ^
+    /// ```
+    pub fn format_error(&self, source: &str, err: &BellandeError) -> String {
+        let (line, column, message) = match err {
+            BellandeError::Parse {
+                line,
+                column,
+                message,
+            } => (*line, *column, message),
+            other => return format!("error: {}", other),
+        };
+        let text = source.lines().nth(line.saturating_sub(1)).unwrap_or("");
+        let gutter = " ".repeat(line.to_string().len());
+        let caret_offset: String = text
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        format!(
+            "error: {}\n{}--> line {}, column {}\n{} |\n{} | {}\n{} | {}^",
+            message, gutter, line, column, gutter, line, text, gutter, caret_offset
+        )
+    }
+
+    fn parse_lines(
+        &self,
+        lines: &[&str],
+        options: &ParseOptions,
+    ) -> Result<(BellandeValue, Vec<BellandeWarning>), BellandeError> {
+        let mut state = ParseState {
+            options,
+            lines,
+            next_line: 0,
+            root: BellandeValue::Map(BTreeMap::new()),
+            stack: Vec::new(),
+            child_indents: Vec::new(),
+            nodes: 0,
+            warnings: Vec::new(),
+            line: 0,
+            column: 0,
+        };
+
+        while let Some(line) = lines.get(state.next_line) {
+            let index = state.next_line;
+            state.next_line += 1;
+            if index > 0 && index.is_multiple_of(ProgressHook::INTERVAL) {
+                if let Some(progress) = &options.progress {
+                    progress.report(index);
+                }
+            }
+            let stripped = line.trim();
+            if index == 0 && line.starts_with("#!") {
+                continue;
+            }
+            if stripped.is_empty() || self.is_comment(stripped, options) {
+                continue;
+            }
+            if stripped == "---" && state.line == 0 {
+                continue;
+            }
+
+            let is_first = state.line == 0;
+            let margin = line.len() - line.trim_start().len();
+            let indent = Self::columns(&line[..margin], options.tab_width);
+            state.line = index + 1;
+            state.column = line[..margin].chars().count() + 1;
+            if line[..margin].contains('\t') && line[..margin].contains(' ') {
+                state.warn(
+                    WarningKind::MixedIndentation,
+                    format!(
+                    "indentation mixes tabs and spaces; read as {} columns with tab stops every {}",
+                    indent, options.tab_width
+                ),
+                );
+            }
+
+            let is_item = self.is_item_line(stripped);
+            let mut open = state.stack.len();
+            while open > 0 && indent <= state.stack[open - 1].indent {
+                open -= 1;
+            }
+            if is_item && self.holds_aligned_items(&state, open, indent) {
+                open += 1;
+            }
+            self.close_blocks(&mut state, open);
+
+            let depth = state.stack.len();
+            state.child_indents.truncate(depth + 1);
+            state.child_indents.resize(depth + 1, None);
+            match state.child_indents[depth] {
+                Some(expected) if expected != indent => state.warn(
+                    WarningKind::IndentationMismatch,
+                    format!(
+                        "indentation of {} does not match the {} of earlier siblings",
+                        indent, expected
+                    ),
+                ),
+                Some(_) => {}
+                None => state.child_indents[depth] = Some(indent),
+            }
+
+            if is_first && is_item {
+                *state.target() = BellandeValue::Null;
+            } else if is_first && self.is_root_scalar(stripped, options) {
+                *state.target() = self.parse_root_scalar(&mut state, stripped)?;
+                break;
+            }
+
+            let result = if is_item {
+                let item = stripped[1..].trim_start();
+                let item_start = margin + stripped.len() - item.len();
+                let item_indent = Self::columns(&line[..item_start], options.tab_width);
+                self.parse_list_item(&mut state, indent, item_indent, item)
+            } else if let Some(colon_pos) = self.find_separator(stripped, options.kv_separator) {
+                let (key, value) = stripped.split_at(colon_pos);
+                let value = &value[options.kv_separator.len_utf8()..];
+                self.parse_entry(&mut state, indent, key, value)
+            } else if let Some(value) = stripped.strip_prefix('-') {
+                self.parse_list_item(&mut state, indent, indent, value.trim())
+            } else {
+                Err(state.error("expected `key: value` or `- item`"))
+            };
+            match result {
+                Err(BellandeError::Parse {
+                    line,
+                    column,
+                    message,
+                }) if options.lenient => {
+                    // A broken flow collection or block scalar may have read
+                    // ahead; only its first line is dropped.
+                    state.next_line = index + 1;
+                    self.close_blocks(&mut state, depth);
+                    state.warnings.push(BellandeWarning {
+                        line,
+                        column,
+                        kind: WarningKind::Skipped,
+                        message: format!("skipped: {}", message),
+                    });
+                }
+                result => result?,
+            }
+        }
+
+        self.close_blocks(&mut state, 0);
+        if let Some(progress) = &options.progress {
+            progress.report(lines.len());
+        }
+        let mut root = state.root;
+        if let Some(profile) = &options.profile {
+            self.resolve_profile(&mut root, profile);
+        }
+        if options.interpolate {
+            root = root.resolve_references()?;
+        }
+        if options.lowercase_keys {
+            root = root.lowercase_keys()?;
+        }
+        Ok((root, state.warnings))
+    }
+
+    /// Whether a trimmed line is a `- item`, as opposed to a key that starts
+    /// with `-` such as `-x: 1`.
+    fn is_item_line(&self, stripped: &str) -> bool {
+        stripped == "-" || stripped.starts_with("- ") || stripped.starts_with("-\t")
+    }
+
+    /// Whether the block at `index` was opened by a `key:` whose list items
+    /// may sit at the key's own indentation, as in `key:\n- a`.
+    fn holds_aligned_items(&self, state: &ParseState, index: usize, indent: usize) -> bool {
+        let Some(block) = state.stack.get(index) else {
+            return false;
+        };
+        let parent = state.stack[..index]
+            .iter()
+            .rev()
+            .find_map(|block| block.value.as_ref())
+            .unwrap_or(&state.root);
+        block.indent == indent
+            && matches!(parent, BellandeValue::Map(_))
+            && matches!(
+                block.value,
+                Some(BellandeValue::List(_) | BellandeValue::Null)
+            )
+    }
+
+    /// A document whose first line is a flow collection, a quoted string or
+    /// has no `:` is a single root value rather than a map. Only comments may
+    /// follow it.
+    fn is_root_scalar(&self, stripped: &str, options: &ParseOptions) -> bool {
+        let value = self.strip_inline_comment(stripped, options);
+        value.starts_with(['{', '['])
+            || (value.starts_with('"') && self.trailing_content(value).is_none())
+            || !value.contains(options.kv_separator)
+    }
+
+    fn parse_root_scalar(
+        &self,
+        state: &mut ParseState,
+        first: &str,
+    ) -> Result<BellandeValue, BellandeError> {
+        let value = if first.starts_with(['{', '[']) {
+            self.parse_flow(state, first)?
+        } else {
+            let value = self.strip_inline_comment(first, state.options);
+            self.parse_scalar_value(state, value)?
+        };
+        while let Some(line) = state.lines.get(state.next_line) {
+            state.next_line += 1;
+            let stripped = line.trim();
+            if !stripped.is_empty() && !self.is_comment(stripped, state.options) {
+                let indent = line.len() - line.trim_start().len();
+                state.line = state.next_line;
+                state.column = line[..indent].chars().count() + 1;
+                return Err(state.error("unexpected content after the root value"));
+            }
+        }
+        Ok(value)
+    }
+
+    fn parse_entry(
+        &self,
+        state: &mut ParseState,
+        indent: usize,
+        key: &str,
+        value: &str,
+    ) -> Result<(), BellandeError> {
+        if key.trim().is_empty() {
+            return Err(state.error("missing key before `:`"));
+        }
+        let mut segments = self.key_segments(key.trim(), state.options);
+        let key = segments.pop().unwrap();
+        let depth = state.stack.len();
+        for segment in segments {
+            self.open_block(state, indent, segment);
+        }
+        let value = value.trim();
+
+        if value.starts_with(['{', '[']) {
+            let flow_value = self.parse_flow(state, value)?;
+            self.insert_entry(state, &key, flow_value)?;
+            self.close_blocks(state, depth);
+            return Ok(());
+        }
+
+        let value = self.strip_inline_comment(value, state.options);
+        if !value.is_empty() {
+            let parsed_value = self.parse_scalar_value(state, value)?;
+            self.insert_entry(state, &key, parsed_value)?;
+            self.close_blocks(state, depth);
+        } else if let Some(raw) = self.take_raw_block(state, indent) {
+            self.insert_entry(state, &key, raw)?;
+            self.close_blocks(state, depth);
+        } else {
+            let placeholder = match state.options.null_style {
+                NullStyle::Empty => BellandeValue::Null,
+                _ => BellandeValue::List(Vec::new()),
+            };
+            self.insert_entry(state, &key, placeholder)?;
+            self.open_block(state, indent, key);
+        }
+        Ok(())
+    }
+
+    /// Under [`ParseOptions::max_parse_depth_materialized`], takes the block
+    /// below a `key:` at `indent` as a raw value instead of parsing it.
+    fn take_raw_block(&self, state: &mut ParseState, indent: usize) -> Option<BellandeValue> {
+        let limit = state.options.max_parse_depth_materialized?;
+        if state.stack.len() + 1 < limit {
+            return None;
+        }
+        let indent_of = |line: &str| line.len() - line.trim_start().len();
+        let mut end = state.next_line;
+        for (index, line) in state.lines.iter().enumerate().skip(state.next_line) {
+            let stripped = line.trim();
+            if stripped.is_empty() || self.is_comment(stripped, state.options) {
+                continue;
+            }
+            if Self::columns(&line[..indent_of(line)], state.options.tab_width) <= indent {
+                break;
+            }
+            end = index + 1;
+        }
+        let block = &state.lines[state.next_line..end];
+        let margin = block
+            .iter()
+            .filter(|line| {
+                let stripped = line.trim();
+                !stripped.is_empty() && !self.is_comment(stripped, state.options)
+            })
+            .map(|line| indent_of(line))
+            .min()?;
+        state.next_line = end;
+        let lines: Vec<&str> = block
+            .iter()
+            .map(|line| line[indent_of(line).min(margin)..].trim_end())
+            .collect();
+        Some(BellandeValue::Raw(lines.join("\n")))
+    }
+
+    /// The column `prefix` ends at, with tabs advancing to the next multiple
+    /// of `tab_width`.
+    fn columns(prefix: &str, tab_width: usize) -> usize {
+        let tab_width = tab_width.max(1);
+        prefix.chars().fold(0, |column, c| {
+            if c == '\t' {
+                (column / tab_width + 1) * tab_width
+            } else {
+                column + 1
+            }
+        })
+    }
+
+    /// Handles `- value` as well as `- key: value`, which starts a map item
+    /// whose remaining keys follow on lines indented past the dash. Both forms
+    /// may be mixed within the same list. A bare `-` (or `- -`) opens an item
+    /// whose content is the indented block below it; with no such block the
+    /// item is null.
+    fn parse_list_item(
+        &self,
+        state: &mut ParseState,
+        indent: usize,
+        item_indent: usize,
+        item: &str,
+    ) -> Result<(), BellandeError> {
+        if state.stack.is_empty() && matches!(state.root, BellandeValue::Map(_)) {
+            return Err(state.error("list item without an enclosing `key:`"));
+        }
+        // `- # note` is a bare dash carrying a comment.
+        let item = if self.is_comment(item, state.options) {
+            ""
+        } else {
+            item
+        };
+
+        if item.is_empty() || item == "-" || item.starts_with("- ") || item.starts_with("-\t") {
+            let index = self.append_item(state, BellandeValue::Null)?;
+            self.open_block(state, indent, index.to_string());
+            if item.is_empty() {
+                return Ok(());
+            }
+            let nested = item[1..].trim_start();
+            let nested_indent = item_indent + (item.len() - nested.len());
+            return self.parse_list_item(state, item_indent, nested_indent, nested);
+        }
+
+        let separator = state.options.kv_separator;
+        match self.find_item_key(item, separator) {
+            Some(colon_pos) => {
+                let index = self.append_item(state, BellandeValue::Map(BTreeMap::new()))?;
+                self.open_block(state, indent, index.to_string());
+                self.parse_entry(
+                    state,
+                    item_indent,
+                    &item[..colon_pos],
+                    &item[colon_pos + separator.len_utf8()..],
+                )
+            }
+            None => {
+                let parsed_value = if item.starts_with(['{', '[']) {
+                    self.parse_flow(state, item)?
+                } else {
+                    let value = self.strip_inline_comment(item, state.options);
+                    self.parse_scalar_value(state, value)?
+                };
+                self.append_item(state, parsed_value).map(|_| ())
+            }
+        }
+    }
+
+    fn append_item(
+        &self,
+        state: &mut ParseState,
+        value: BellandeValue,
+    ) -> Result<usize, BellandeError> {
+        state.count_nodes(1)?;
+        self.append_to_list(state.target(), value)
+            .ok_or_else(|| state.error("list item inside a map"))
+    }
+
+    /// A block holds either list items or map entries, never both: whichever
+    /// comes first decides, and a sibling of the other kind at the same
+    /// indent is an error in either order rather than being dropped.
+    fn insert_entry(
+        &self,
+        state: &mut ParseState,
+        key: &str,
+        value: BellandeValue,
+    ) -> Result<(), BellandeError> {
+        state.count_nodes(1)?;
+        let replaced = self
+            .insert_value(state.target(), key, value)
+            .ok_or_else(|| state.error(format!("map entry `{}` inside a list", key)))?;
+        if replaced {
+            state.warn(
+                WarningKind::DuplicateKey,
+                format!("duplicate key `{}` overwrites the earlier value", key),
+            );
+        }
+        Ok(())
+    }
+
+    /// Parses a `{ ... }` or `[ ... ]` block starting at `first`. The block
+    /// may continue over the following lines until its brackets balance;
+    /// inside it indentation is ignored and entries are separated by commas
+    /// or newlines.
+    fn parse_flow(
+        &self,
+        state: &mut ParseState,
+        first: &str,
+    ) -> Result<BellandeValue, BellandeError> {
+        let mut text = first.to_string();
+        while self.flow_depth(&text, state.options) > 0 {
+            let line = state
+                .lines
+                .get(state.next_line)
+                .ok_or_else(|| state.error("unclosed `{` or `[`"))?;
+            state.next_line += 1;
+            text.push('\n');
+            text.push_str(line);
+        }
+
+        let mut parser = FlowParser {
+            format: self,
+            options: state.options,
+            text: &text,
+            pos: 0,
+            nodes: 0,
+        };
+        let value = parser.parse_document().map_err(|(pos, message)| {
+            let before = &text[..pos];
+            match before.rfind('\n') {
+                Some(line_start) => BellandeError::parse(
+                    state.line + before.matches('\n').count(),
+                    before[line_start + 1..].chars().count() + 1,
+                    message,
+                ),
+                None => state.error(message),
+            }
+        })?;
+        state.count_nodes(parser.nodes)?;
+        Ok(value)
+    }
+
+    /// Finds the `"` that closes a string whose opening quote precedes
+    /// `rest`, skipping backslash-escaped characters.
+    fn closing_quote(&self, rest: &str) -> Option<usize> {
+        let mut escaped = false;
+        for (pos, c) in rest.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => return Some(pos),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn flow_depth(&self, text: &str, options: &ParseOptions) -> isize {
+        let mut depth = 0;
+        let mut in_quotes = false;
+        let mut escaped = false;
+        let mut after_whitespace = true;
+        let mut in_comment = false;
+        for (pos, c) in text.char_indices() {
+            if in_comment {
+                in_comment = c != '\n';
+            } else if in_quotes {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else {
+                    in_quotes = c != '"';
+                }
+            } else if c == '"' {
+                in_quotes = true;
+            } else if after_whitespace && self.is_comment(&text[pos..], options) {
+                in_comment = true;
+            } else if c == '{' || c == '[' {
+                depth += 1;
+            } else if c == '}' || c == ']' {
+                depth -= 1;
+            }
+            after_whitespace = c.is_whitespace();
+        }
+        depth
+    }
+
+    fn parse_scalar_value(
+        &self,
+        state: &mut ParseState,
+        value: &str,
+    ) -> Result<BellandeValue, BellandeError> {
+        if let Some(trailing) = self.trailing_content(value) {
+            let message = format!("unexpected trailing content `{}` in `{}`", trailing, value);
+            match state.options.trailing_content {
+                TrailingContent::Allow => {}
+                TrailingContent::Warn => state.warn(WarningKind::TrailingContent, message),
+                TrailingContent::Reject => return Err(state.error(message)),
+            }
+        }
+        let resolved = self
+            .resolve_scalar(value, state.options)
+            .map_err(|message| state.error(message))?;
+        if matches!(resolved, BellandeValue::String(_)) && self.looks_numeric(value) {
+            state.warn(
+                WarningKind::NumericString,
+                format!("`{}` looks like a number but is read as a string", value),
+            );
+        }
+        Ok(resolved)
+    }
+
+    /// Catches digit strings the numeric grammar rejects, such as `1,000`,
+    /// `1_000` or an integer too large for `i64`. Dates and versions like
+    /// `1.2.3` are not flagged.
+    fn looks_numeric(&self, value: &str) -> bool {
+        let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+        digits.starts_with(|c: char| c.is_ascii_digit())
+            && digits
+                .chars()
+                .all(|c| c.is_ascii_digit() || matches!(c, '_' | ',' | '.'))
+            && digits.matches('.').count() <= 1
+            && self.parse_number(value).is_none()
+    }
+
+    /// Applies explicit tags and the option-dependent typing rules before
+    /// falling back to the default inference of `parse_value`. Fails only
+    /// for a tagged value that doesn't fit its tag.
+    fn resolve_scalar(&self, value: &str, options: &ParseOptions) -> Result<BellandeValue, String> {
+        if let Some((tag, rest)) = self.split_tag(value) {
+            let typed = match (tag, self.parse_value(rest)) {
+                ("int", number @ BellandeValue::Integer(_)) => Some(number),
+                ("float", BellandeValue::Integer(i)) => Some(BellandeValue::Float(i as f64)),
+                ("float", number @ BellandeValue::Float(_)) => Some(number),
+                // Only tagged, so that a bare `inf` stays a string.
+                ("float", BellandeValue::String(_)) if !rest.starts_with('"') => rest
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|f| !f.is_finite())
+                    .map(BellandeValue::Float),
+                ("bool", boolean @ BellandeValue::Boolean(_)) => Some(boolean),
+                ("str", string @ BellandeValue::String(_)) if rest.starts_with('"') => Some(string),
+                ("str", _) => Some(BellandeValue::String(rest.to_string())),
+                ("null", _) if rest.is_empty() => Some(BellandeValue::Null),
+                _ => None,
+            };
+            return typed.ok_or_else(|| format!("`{}` is not a valid !{}", rest, tag));
+        }
+        let custom = match &options.scalar_resolver {
+            Some(resolver) if !value.starts_with('"') => (resolver.0)(value),
+            _ => None,
+        };
+        if let Some(resolved) = custom {
+            return Ok(resolved);
+        }
+        if options.preserve_leading_zeros && self.has_leading_zero(value) {
+            return Ok(BellandeValue::String(value.to_string()));
+        }
+        if !options.infer_types {
+            return Ok(
+                match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+                    Some(inner) => BellandeValue::String(self.unescape(inner)),
+                    None => BellandeValue::String(value.to_string()),
+                },
+            );
+        }
+        if value == "~" && options.null_style == NullStyle::Tilde {
+            return Ok(BellandeValue::Null);
+        }
+        Ok(self.parse_value(value))
+    }
+
+    /// Splits `!int 42` into `("int", "42")`. Only the `!int`, `!float`,
+    /// `!bool`, `!str` and `!null` tags are recognized; anything else is an
+    /// ordinary value.
+    fn split_tag<'a>(&self, value: &'a str) -> Option<(&'static str, &'a str)> {
+        let rest = value.strip_prefix('!')?;
+        ["int", "float", "bool", "str", "null"]
+            .into_iter()
+            .find_map(|tag| {
+                let after = rest.strip_prefix(tag)?;
+                (after.is_empty() || after.starts_with(char::is_whitespace))
+                    .then(|| (tag, after.trim()))
+            })
+    }
+
+    fn has_leading_zero(&self, value: &str) -> bool {
+        let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+        let mut chars = digits.chars();
+        chars.next() == Some('0')
+            && chars.next().is_some_and(|c| c.is_ascii_digit())
+            && value.parse::<f64>().is_ok()
+    }
+
+    fn trailing_content<'a>(&self, value: &'a str) -> Option<&'a str> {
+        let value = self.split_tag(value).map_or(value, |(_, rest)| rest);
+        let trailing = match value.strip_prefix('"') {
+            Some(rest) => &rest[self.closing_quote(rest)? + 1..],
+            None => value.split_once(char::is_whitespace)?.1,
+        };
+        let trailing = trailing.trim();
+        (!trailing.is_empty()).then_some(trailing)
+    }
+
+    fn find_item_key(&self, item: &str, separator: char) -> Option<usize> {
+        if item.starts_with(['"', '{', '[']) {
+            return None;
+        }
+        let colon_pos = self.find_separator(item, separator)?;
+        let rest = &item[colon_pos + separator.len_utf8()..];
+        if colon_pos > 0 && (rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+            Some(colon_pos)
+        } else {
+            None
+        }
+    }
+
+    /// Finds the first `separator` that is not inside a double-quoted span.
+    fn find_separator(&self, text: &str, separator: char) -> Option<usize> {
+        let mut pos = 0;
+        while let Some(offset) = text[pos..].find([separator, '"']) {
+            let at = pos + offset;
+            if text[at..].starts_with(separator) {
+                return Some(at);
+            }
+            pos = at + 2 + self.closing_quote(&text[at + 1..])?;
+        }
+        None
+    }
+
+    fn resolve_profile(&self, value: &mut BellandeValue, profile: &str) {
+        match value {
+            BellandeValue::Map(map) => {
+                let profiled: Vec<String> = map
+                    .keys()
+                    .filter(|key| key.contains('@'))
+                    .cloned()
+                    .collect();
+                for key in profiled {
+                    let entry = map.remove(&key).unwrap_or(BellandeValue::Null);
+                    let (bare, key_profile) = key.rsplit_once('@').unwrap_or((&key, ""));
+                    if key_profile == profile {
+                        map.insert(bare.to_string(), entry);
+                    }
+                }
+                for child in map.values_mut() {
+                    self.resolve_profile(child, profile);
+                }
+            }
+            BellandeValue::List(list) => {
+                for item in list {
+                    self.resolve_profile(item, profile);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn is_comment(&self, stripped: &str, options: &ParseOptions) -> bool {
+        options
+            .comment_prefixes
+            .iter()
+            .any(|prefix| !prefix.is_empty() && stripped.starts_with(prefix.as_str()))
+    }
+
+    fn strip_inline_comment<'a>(&self, value: &'a str, options: &ParseOptions) -> &'a str {
+        let mut in_quotes = false;
+        let mut escaped = false;
+        let mut after_whitespace = false;
+        for (pos, c) in value.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if in_quotes && c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_quotes = !in_quotes;
+            } else if !in_quotes && after_whitespace && self.is_comment(&value[pos..], options) {
+                return value[..pos].trim_end();
+            }
+            after_whitespace = c.is_whitespace();
+        }
+        value
+    }
+
+    fn key_segments(&self, key: &str, options: &ParseOptions) -> Vec<String> {
+        if options.expand_dotted_keys {
+            let segments: Vec<&str> = key.split('.').map(str::trim).collect();
+            if segments.iter().all(|segment| !segment.is_empty()) {
+                return segments.into_iter().map(str::to_string).collect();
+            }
+        }
+        vec![key.to_string()]
+    }
+
+    fn insert_value(
+        &self,
+        current: &mut BellandeValue,
+        key: &str,
+        value: BellandeValue,
+    ) -> Option<bool> {
+        if self.is_placeholder(current) {
+            *current = BellandeValue::Map(BTreeMap::new());
+        }
+        match current {
+            BellandeValue::Map(map) => Some(map.insert(key.to_string(), value).is_some()),
+            BellandeValue::List(_) => None,
+            _ => Some(false),
+        }
+    }
+
+    fn append_to_list(&self, current: &mut BellandeValue, value: BellandeValue) -> Option<usize> {
+        if current.is_null() {
+            *current = BellandeValue::List(Vec::new());
+        }
+        if let BellandeValue::List(list) = current {
+            list.push(value);
+            return Some(list.len() - 1);
+        }
+        None
+    }
+
+    /// Open blocks start out as an empty list (`key:`) or null (a bare `-`)
+    /// until their first child decides what they hold.
+    fn is_placeholder(&self, value: &BellandeValue) -> bool {
+        match value {
+            BellandeValue::List(list) => list.is_empty(),
+            BellandeValue::Null => true,
+            _ => false,
+        }
+    }
+
+    /// Opens a block for `key` inside the current target, creating a map for
+    /// a missing key and turning a placeholder target into a map first.
+    fn open_block(&self, state: &mut ParseState, indent: usize, key: String) {
+        let parent = state.target();
+        if self.is_placeholder(parent) {
+            *parent = BellandeValue::Map(BTreeMap::new());
+        }
+        let value = match parent {
+            BellandeValue::Map(map) => Some(
+                map.remove(&key)
+                    .unwrap_or_else(|| BellandeValue::Map(BTreeMap::new())),
+            ),
+            BellandeValue::List(list) => key
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| list.get_mut(index))
+                .map(|item| core::mem::replace(item, BellandeValue::Null)),
+            _ => None,
+        };
+        state.stack.push(Block { indent, key, value });
+    }
+
+    /// Closes blocks until `depth` remain, putting each value back into its
+    /// parent.
+    fn close_blocks(&self, state: &mut ParseState, depth: usize) {
+        while state.stack.len() > depth {
+            let Some(block) = state.stack.pop() else {
+                break;
+            };
+            let Some(value) = block.value else {
+                continue;
+            };
+            match state.target() {
+                BellandeValue::Map(map) => {
+                    map.insert(block.key, value);
+                }
+                BellandeValue::List(list) => {
+                    if let Some(item) = block
+                        .key
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|i| list.get_mut(i))
+                    {
+                        *item = value;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_value(&self, value: &str) -> BellandeValue {
+        if value.eq_ignore_ascii_case("true") {
+            BellandeValue::Boolean(true)
+        } else if value.eq_ignore_ascii_case("false") {
+            BellandeValue::Boolean(false)
+        } else if value.eq_ignore_ascii_case("null") {
+            BellandeValue::Null
+        } else if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            BellandeValue::String(self.unescape(&value[1..value.len() - 1]))
+        } else if let Some(number) = self.parse_number(value) {
+            number
+        } else {
+            BellandeValue::String(value.to_string())
+        }
+    }
+
+    /// Recognizes the numeric grammar, independent of platform and locale:
+    ///
+    /// - integer: `[+-]?[0-9]+`, within the range of `i64`
+    /// - float: `[+-]?` then `D.D`, `D.` or `.D` (`D` being one or more
+    ///   digits) and an optional exponent `[eE][+-]?[0-9]+`; or an integer
+    ///   with an exponent. The decimal separator is always `.`
+    ///
+    /// Integers that overflow `i64`, floats that overflow to infinity, and
+    /// words such as `inf` or `NaN` are not numbers and stay strings, so no
+    /// value silently loses precision.
+    fn parse_number(&self, value: &str) -> Option<BellandeValue> {
+        let unsigned = value.strip_prefix(['+', '-']).unwrap_or(value);
+        let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+            Some(pos) => (&unsigned[..pos], Some(&unsigned[pos + 1..])),
+            None => (unsigned, None),
+        };
+        let (whole, fraction) = match mantissa.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (mantissa, None),
+        };
+        let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if !digits(whole)
+            || !fraction.is_none_or(digits)
+            || whole.len() + fraction.map_or(0, str::len) == 0
+        {
+            return None;
+        }
+        if let Some(exponent) = exponent {
+            let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            if exponent.is_empty() || !digits(exponent) {
+                return None;
+            }
+        }
+
+        if fraction.is_none() && exponent.is_none() {
+            return value.parse::<i64>().ok().map(BellandeValue::Integer);
+        }
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|float| float.is_finite())
+            .map(BellandeValue::Float)
+    }
+
+    /// Decodes `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\u{1F600}` and `\uXXXX`
+    /// (including UTF-16 surrogate pairs) inside a quoted string. Anything
+    /// else after a backslash is kept literally, so `"C:\dir"` stays as is.
+    fn unescape(&self, raw: &str) -> String {
+        let mut out = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(pos) = rest.find('\\') {
+            out.push_str(&rest[..pos]);
+            let after = &rest[pos + 1..];
+            match self.decode_escape(after) {
+                Some((decoded, consumed)) => {
+                    out.push(decoded);
+                    rest = &after[consumed..];
+                }
+                None => {
+                    out.push('\\');
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+
+    fn decode_escape(&self, after: &str) -> Option<(char, usize)> {
+        let simple = match after.chars().next()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            'u' => return self.decode_unicode_escape(&after[1..]),
+            _ => return None,
+        };
+        Some((simple, 1))
+    }
+
+    fn decode_unicode_escape(&self, digits: &str) -> Option<(char, usize)> {
+        let hex_value = |hex: &str| {
+            if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            u32::from_str_radix(hex, 16).ok()
+        };
+
+        if let Some(braced) = digits.strip_prefix('{') {
+            let end = braced.find('}').filter(|end| *end <= 6)?;
+            let ch = char::from_u32(hex_value(&braced[..end])?)?;
+            return Some((ch, end + 3));
+        }
+
+        let code = hex_value(digits.get(..4)?)?;
+        if (0xD800..0xDC00).contains(&code) {
+            let low = hex_value(digits.get(4..10)?.strip_prefix("\\u")?)?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return None;
+            }
+            let ch = char::from_u32(0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00))?;
+            return Some((ch, 11));
+        }
+        Some((char::from_u32(code)?, 5))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_bellande<P: AsRef<Path>>(
+        &self,
+        data: &BellandeValue,
+        file_path: P,
+    ) -> Result<(), std::io::Error> {
+        self.write_bellande_with_options(data, file_path, &WriteOptions::default())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_bellande_with_options<P: AsRef<Path>>(
+        &self,
+        data: &BellandeValue,
+        file_path: P,
+        options: &WriteOptions,
+    ) -> Result<(), std::io::Error> {
+        let mut content = String::new();
+        self.write_bellande_to_string_with_options(data, &mut content, options)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()))?;
+        fs::write(file_path, content)
+    }
+
+    /// Writes a lossless document in its normalized [`BellandeDocument::format`]
+    /// style, so comments and the blank lines separating sections survive.
+    #[cfg(feature = "std")]
+    pub fn write_document<P: AsRef<Path>>(
+        &self,
+        document: &BellandeDocument,
+        file_path: P,
+    ) -> Result<(), BellandeError> {
+        fs::write(file_path, document.format())?;
+        Ok(())
+    }
+
+    /// Writes `data` to a temporary file next to `file_path` and renames it
+    /// into place, so readers see either the old or the new file but never a
+    /// truncated one.
+    ///
+    /// Each call gets its own temporary file, created exclusively, so
+    /// concurrent writers never share one. The new file keeps the
+    /// permissions of the file it replaces. The temporary file is flushed
+    /// with `fsync` before the rename. On Unix the containing directory is
+    /// synced afterwards as well, which makes the rename itself survive a
+    /// power loss; elsewhere the rename is atomic but its durability is left
+    /// to the operating system.
+    #[cfg(feature = "std")]
+    pub fn write_bellande_atomic<P: AsRef<Path>>(
+        &self,
+        data: &BellandeValue,
+        file_path: P,
+    ) -> Result<(), BellandeError> {
+        let file_path = file_path.as_ref();
+        let dir = match file_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let file_name = file_path.file_name().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not a file path", file_path.display()),
+            )
+        })?;
+        let content = self.to_bellande_string(data, 0, &WriteOptions::default());
+        let permissions = fs::metadata(file_path).ok().map(|meta| meta.permissions());
+        let (mut file, temp_path) = Self::create_temp_file(dir, &file_name.to_string_lossy())?;
+
+        let result = (|| {
+            if let Some(permissions) = permissions {
+                file.set_permissions(permissions)?;
+            }
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+            fs::rename(&temp_path, file_path)
+        })();
+        if let Err(err) = result {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err.into());
+        }
+
+        #[cfg(unix)]
+        fs::File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Creates a new, empty `.{name}.{pid}.{n}.tmp` in `dir`, never opening
+    /// an existing file or following a symlink. On Unix it is readable by
+    /// the owner only until the caller sets its permissions.
+    #[cfg(feature = "std")]
+    fn create_temp_file(dir: &Path, name: &str) -> std::io::Result<(fs::File, std::path::PathBuf)> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        for _ in 0..100 {
+            let temp_path = dir.join(format!(
+                ".{}.{}.{}.tmp",
+                name,
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            match options.open(&temp_path) {
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                result => return result.map(|file| (file, temp_path)),
+            }
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("no free temporary file name in {}", dir.display()),
+        ))
+    }
+
+    /// Appends `data` as [`BellandeFormat::write_bellande`] would write it
+    /// to `buf`, so several fragments can be assembled in one buffer without
+    /// an intermediate `String` for each. Nothing is added before or after
+    /// the document, including a trailing newline.
+    pub fn write_bellande_to_string(&self, data: &BellandeValue, buf: &mut String) {
+        self.write_into(data, 0, &WriteOptions::default(), buf);
+    }
+
+    /// Appends `data` to `buf` as
+    /// [`BellandeFormat::write_bellande_with_options`] would write it,
+    /// including the shebang, header and `---` marker that `options` ask
+    /// for. Fails only if [`WriteOptions::lowercase_keys`] makes two keys
+    /// collide, in which case `buf` is left unchanged.
+    pub fn write_bellande_to_string_with_options(
+        &self,
+        data: &BellandeValue,
+        buf: &mut String,
+        options: &WriteOptions,
+    ) -> Result<(), BellandeError> {
+        let lowercased;
+        let data = if options.lowercase_keys {
+            lowercased = data.lowercase_keys()?;
+            &lowercased
+        } else {
+            data
+        };
+        if let Some(shebang) = &options.shebang {
+            buf.push_str(shebang);
+            buf.push('\n');
+        }
+        if let Some(header) = &options.header {
+            buf.push_str(header.trim_end_matches('\n'));
+            buf.push('\n');
+        }
+        if options.document_start {
+            buf.push_str("---\n");
+        }
+        self.write_into(data, 0, options, buf);
+        Ok(())
+    }
+
+    fn to_bellande_string(
+        &self,
+        data: &BellandeValue,
+        indent: usize,
+        options: &WriteOptions,
+    ) -> String {
+        let mut out = String::new();
+        self.write_into(data, indent, options, &mut out);
+        out
+    }
+
+    /// Appends `data` to `out` in the block style with its top level at
+    /// `indent` spaces.
+    fn write_into(
+        &self,
+        data: &BellandeValue,
+        indent: usize,
+        options: &WriteOptions,
+        out: &mut String,
+    ) {
+        let start = out.len();
+        self.write_with(
+            data,
+            &mut BlockWriter {
+                options,
+                out,
+                start,
+                base_indent: indent,
+                frames: Vec::new(),
+                header: String::new(),
+                dash_prefix: String::new(),
+            },
+        );
+    }
+
+    /// Feeds `data` to `writer` one piece at a time: each map and list
+    /// between its `begin_*` and `end_*` call, each entry as its key followed
+    /// by its value and each list item as its index followed by its value.
+    /// Map entries come in the order the built-in writer uses. Nested values
+    /// are walked with an explicit stack rather than recursion, so
+    /// arbitrarily deep documents cannot overflow the call stack.
+    pub fn write_with<W: BellandeWriter>(&self, data: &BellandeValue, writer: &mut W) {
+        enum Step<'a> {
+            Value(&'a BellandeValue),
+            Key(&'a str),
+            Item(usize),
+            EndMap,
+            EndList,
+        }
+
+        let mut stack = vec![Step::Value(data)];
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Value(BellandeValue::Map(map)) => {
+                    let entries: Vec<(&String, &BellandeValue)> = map.iter().collect();
+                    let keys: Vec<&str> = entries.iter().map(|(key, _)| key.as_str()).collect();
+                    writer.begin_map(&keys);
+                    stack.push(Step::EndMap);
+                    for (key, value) in entries.into_iter().rev() {
+                        stack.push(Step::Value(value));
+                        stack.push(Step::Key(key));
+                    }
+                }
+                Step::Value(BellandeValue::List(list)) => {
+                    writer.begin_list(list.len());
+                    stack.push(Step::EndList);
+                    for (index, item) in list.iter().enumerate().rev() {
+                        stack.push(Step::Value(item));
+                        stack.push(Step::Item(index));
+                    }
+                }
+                Step::Value(scalar) => writer.scalar(scalar),
+                Step::Key(key) => writer.key(key),
+                Step::Item(index) => writer.item(index),
+                Step::EndMap => writer.end_map(),
+                Step::EndList => writer.end_list(),
+            }
+        }
+    }
+
+    fn quote(&self, s: &str, options: &WriteOptions) -> String {
+        let mut quoted = String::with_capacity(s.len() + 2);
+        quoted.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                '\r' => quoted.push_str("\\r"),
+                c if c.is_control() || (options.escape_non_ascii && !c.is_ascii()) => {
+                    quoted.push_str(&format!("\\u{{{:X}}}", c as u32))
+                }
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+
+    fn format_value_or_kind(&self, value: &BellandeValue) -> String {
+        match value {
+            BellandeValue::List(_) | BellandeValue::Map(_) => value.type_name().to_string(),
+            scalar => format!("`{}`", self.format_value(scalar, &WriteOptions::default())),
+        }
+    }
+
+    fn format_value(&self, value: &BellandeValue, options: &WriteOptions) -> String {
+        match value {
+            BellandeValue::String(s) => {
+                if options.quote_style == QuotePolicy::Always
+                    || s.is_empty()
+                    || s.starts_with(['{', '[', '"'])
+                    || s.contains(|c: char| {
+                        c.is_control() || (options.escape_non_ascii && !c.is_ascii())
+                    })
+                    || s.contains(' ')
+                    || s.contains(':')
+                    || options
+                        .kv_separator
+                        .is_some_and(|separator| s.contains(separator))
+                    || s.contains('#')
+                    || self.split_tag(s).is_some()
+                    || s == "-"
+                    || s == "---"
+                    || s.starts_with("- ")
+                    || ["true", "false", "null"].contains(&s.to_lowercase().as_str())
+                    || (s == "~" && options.null_style == NullStyle::Tilde)
+                    || s.parse::<i64>().is_ok()
+                    || s.parse::<f64>().is_ok()
+                {
+                    self.quote(s, options)
+                } else {
+                    s.clone()
+                }
+            }
+            BellandeValue::Integer(i) => i.to_string(),
+            BellandeValue::Float(f) => {
+                let magnitude = f.abs();
+                if options.scientific_notation.is_some_and(|limits| {
+                    f.is_finite()
+                        && (magnitude >= limits.at_least
+                            || (magnitude != 0.0 && magnitude < limits.below))
+                }) {
+                    return format!("{:e}", f);
+                }
+                // Both give the shortest text that reads back as the same
+                // bits; `ryu` also pins the exact spelling across releases.
+                #[cfg(feature = "ryu")]
+                let formatted = if f.is_finite() {
+                    ryu::Buffer::new().format_finite(*f).to_string()
+                } else {
+                    f.to_string()
+                };
+                #[cfg(not(feature = "ryu"))]
+                let formatted = f.to_string();
+                if f.is_finite() && !formatted.contains(['.', 'e', 'E']) {
+                    format!("{}.0", formatted)
+                } else {
+                    formatted
+                }
+            }
+            BellandeValue::Boolean(b) => b.to_string().to_lowercase(),
+            BellandeValue::Null => match options.null_style {
+                NullStyle::Word => "null".to_string(),
+                NullStyle::Tilde => "~".to_string(),
+                NullStyle::Empty => String::new(),
+            },
+            // Only the block writer can lay out a raw block; elsewhere it is
+            // shown as a quoted string.
+            BellandeValue::Raw(text) => self.quote(text, options),
+            BellandeValue::List(_) | BellandeValue::Map(_) => unreachable!(),
+        }
+    }
+}
+
+/// The block style of `write_bellande`, built on [`BellandeWriter`].
+struct BlockWriter<'a> {
+    options: &'a WriteOptions,
+    out: &'a mut String,
+    /// Where the output of this writer begins in `out`.
+    start: usize,
+    base_indent: usize,
+    /// For each open map or list, the indentation of its lines and, for
+    /// `align_colons`, the width its keys are padded to.
+    frames: Vec<(usize, usize)>,
+    /// The `key:` or `-` that the next value completes.
+    header: String,
+    /// Text already placed on the current line by enclosing `- ` items, so
+    /// that the first entry of an item is written on the dash line itself.
+    dash_prefix: String,
+}
+
+impl BlockWriter<'_> {
+    fn push_line(&mut self, text: &str) {
+        if self.out.len() > self.start {
+            self.out.push('\n');
+        }
+        if self.dash_prefix.is_empty() {
+            let indent = self.frames.last().map_or(self.base_indent, |frame| frame.0);
+            self.out.extend(core::iter::repeat_n(' ', indent));
+        } else {
+            self.out.push_str(&core::mem::take(&mut self.dash_prefix));
+        }
+        self.out.push_str(text);
+    }
+
+    /// Writes the line introducing a nested map or list, `empty` in place of
+    /// its content if it has none, and returns the indentation of its lines.
+    fn open(&mut self, empty: Option<&str>, step: usize) -> usize {
+        let Some(&(indent, _)) = self.frames.last() else {
+            if let Some(empty) = empty {
+                self.out.push_str(empty);
+            }
+            return self.base_indent;
+        };
+        let header = core::mem::take(&mut self.header);
+        if let Some(empty) = empty {
+            self.push_line(&format!("{} {}", header, empty));
+        } else if header == "-" {
+            if self.dash_prefix.is_empty() {
+                self.dash_prefix = " ".repeat(indent);
+            }
+            self.dash_prefix.push_str("- ");
+        } else {
+            self.push_line(&header);
+        }
+        indent + step
+    }
+}
+
+impl BellandeWriter for BlockWriter<'_> {
+    fn scalar(&mut self, value: &BellandeValue) {
+        if let BellandeValue::Raw(text) = value {
+            let Some(&(indent, _)) = self.frames.last() else {
+                self.out.push_str(text);
+                return;
+            };
+            let header = core::mem::take(&mut self.header);
+            self.push_line(&header);
+            for line in text.lines() {
+                self.out.push('\n');
+                if !line.is_empty() {
+                    self.out.extend(core::iter::repeat_n(' ', indent + 2));
+                    self.out.push_str(line);
+                }
+            }
+            return;
+        }
+        let mut formatted = BellandeFormat.format_value(value, self.options);
+        if self.options.emit_tags {
+            formatted = match value {
+                BellandeValue::Null => "!null".to_string(),
+                BellandeValue::String(_) => format!("!str {}", formatted),
+                BellandeValue::Integer(_) => format!("!int {}", formatted),
+                BellandeValue::Float(_) => format!("!float {}", formatted),
+                BellandeValue::Boolean(_) => format!("!bool {}", formatted),
+                _ => formatted,
+            };
+        }
+        if self.options.type_comments && !formatted.is_empty() {
+            formatted = format!("{}  # {}", formatted, value.type_name());
+        }
+        if self.frames.is_empty() {
+            self.out.push_str(&formatted);
+            return;
+        }
+        let mut line = core::mem::take(&mut self.header);
+        if !formatted.is_empty() {
+            line.push(' ');
+            line.push_str(&formatted);
+        }
+        self.push_line(&line);
+    }
+
+    fn begin_map(&mut self, keys: &[&str]) {
+        let width = if self.options.align_colons {
+            keys.iter()
+                .map(|key| key.chars().count())
+                .filter(|&width| width <= ALIGN_COLONS_MAX_WIDTH)
+                .max()
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        let indent = self.open(keys.is_empty().then_some("{}"), 2);
+        self.frames.push((indent, width));
+    }
+
+    fn key(&mut self, key: &str) {
+        let width = self.frames.last().map_or(0, |frame| frame.1);
+        self.header = match self.options.kv_separator {
+            Some(separator) if separator != ':' => format!("{:<1$} {2}", key, width, separator),
+            _ => format!("{:<1$}:", key, width),
+        };
+    }
+
+    fn end_map(&mut self) {
+        self.frames.pop();
+    }
+
+    fn begin_list(&mut self, len: usize) {
+        let aligned = self.header != "-" && self.options.list_indent == ListIndent::Aligned;
+        let indent = self.open((len == 0).then_some("[]"), if aligned { 0 } else { 2 });
+        self.frames.push((indent, 0));
+    }
+
+    fn item(&mut self, _index: usize) {
+        self.header = "-".to_string();
+    }
+
+    fn end_list(&mut self) {
+        self.frames.pop();
+    }
+}
+
+impl BellandeDocument {
+    /// Sets the top-level `key` to `value` with `comment` written above it,
+    /// one `# ` line per line of the comment. An existing entry for `key` is
+    /// replaced in place, comment included; otherwise the entry is appended.
+    pub fn insert_with_comment(&mut self, key: &str, value: &BellandeValue, comment: &str) {
+        let format = BellandeFormat;
+        let entry = BellandeValue::Map(BTreeMap::from([(key.to_string(), value.clone())]));
+        let source = format.to_bellande_string(&entry, 0, &WriteOptions::default());
+        let Some(mut node) = format.parse_document(&source).nodes.into_iter().next() else {
+            return;
+        };
+        node.leading = comment
+            .lines()
+            .map(|line| Trivia {
+                kind: TriviaKind::Comment(format!(" {}", line)),
+                raw: format!("# {}", line).trim_end().to_string(),
+            })
+            .collect();
+
+        let existing = self.nodes.iter_mut().find(
+            |node| matches!(&node.kind, CstNodeKind::Entry { key: existing, .. } if existing == key),
+        );
+        match existing {
+            Some(existing) => {
+                node.leading.splice(
+                    0..0,
+                    existing
+                        .leading
+                        .iter()
+                        .filter(|trivia| trivia.kind == TriviaKind::Blank)
+                        .cloned(),
+                );
+                *existing = node;
+            }
+            None => self.nodes.push(node),
+        }
+    }
+
+    /// Reorders the entries of the map at the dotted `path` (empty for the
+    /// top level) to follow `order`. Keys missing from `order` come after the
+    /// listed ones in their original relative order, and every entry moves
+    /// together with its leading comments. List items and other lines keep
+    /// their positions, as does an entry written on a `- ` line.
+    pub fn reorder_keys(&mut self, path: &str, order: &[&str]) -> Result<(), BellandeError> {
+        let mut siblings = &mut self.nodes;
+        for segment in path.split('.').filter(|_| !path.is_empty()) {
+            let is_key = |node: &CstNode| matches!(&node.kind, CstNodeKind::Entry { key, .. } if key == segment);
+            let position = siblings.iter().position(is_key).or_else(|| {
+                let index = segment.parse::<usize>().ok()?;
+                (0..siblings.len())
+                    .filter(|&i| matches!(siblings[i].kind, CstNodeKind::Item { .. }))
+                    .nth(index)
+            });
+            let position = position.ok_or_else(|| BellandeError::MissingPath(path.to_string()))?;
+            siblings = &mut siblings[position].children;
+        }
+
+        let rank = |node: &CstNode| match &node.kind {
+            CstNodeKind::Entry { key, .. } => order
+                .iter()
+                .position(|wanted| wanted == key)
+                .unwrap_or(order.len()),
+            _ => order.len(),
+        };
+        let slots: Vec<usize> = (0..siblings.len())
+            .filter(|&index| matches!(siblings[index].kind, CstNodeKind::Entry { .. }))
+            .collect();
+        let mut entries: Vec<CstNode> = slots
+            .iter()
+            .rev()
+            .map(|&index| siblings.remove(index))
+            .collect();
+        entries.reverse();
+        entries.sort_by_key(rank);
+        for (index, entry) in slots.into_iter().zip(entries) {
+            siblings.insert(index, entry);
+        }
+        Ok(())
+    }
+
+    /// Merges `other` into this document with the rules of
+    /// [`BellandeValue::merge`] while keeping the comments of both sides.
+    ///
+    /// An entry only `other` has is appended together with its comments,
+    /// re-indented to fit. When both sides have a key, the entry from `other`
+    /// wins, and so do its comments if it has any; an overriding entry
+    /// without comments keeps the ones already documenting the key. Blank
+    /// lines of this document stay where they are, and trailing comments of
+    /// `other` are appended at the end.
+    pub fn merge(&mut self, other: BellandeDocument) {
+        if Self::is_map_level(&self.nodes) && Self::is_map_level(&other.nodes) {
+            let indent = self.nodes.first().map_or(0, |node| node.indent);
+            Self::merge_nodes(&mut self.nodes, other.nodes, indent);
+        } else {
+            self.nodes = other.nodes;
+        }
+        self.trailing.extend(
+            other
+                .trailing
+                .into_iter()
+                .filter(|trivia| matches!(trivia.kind, TriviaKind::Comment(_))),
+        );
+    }
+
+    fn merge_nodes(base: &mut Vec<CstNode>, overlay: Vec<CstNode>, indent: usize) {
+        for mut node in overlay {
+            let key = match &node.kind {
+                CstNodeKind::Entry { key, .. } => key.clone(),
+                CstNodeKind::Text(text) if text == "---" => continue,
+                _ => {
+                    Self::reindent(&mut node, indent);
+                    base.push(node);
+                    continue;
+                }
+            };
+            let existing = base.iter_mut().find(
+                |existing| matches!(&existing.kind, CstNodeKind::Entry { key: k, .. } if *k == key),
+            );
+            let Some(existing) = existing else {
+                Self::reindent(&mut node, indent);
+                base.push(node);
+                continue;
+            };
+
+            Self::reindent(&mut node, existing.indent);
+            let has_comments = |node: &CstNode| {
+                node.leading
+                    .iter()
+                    .any(|trivia| matches!(trivia.kind, TriviaKind::Comment(_)))
+            };
+            if has_comments(&node) {
+                existing
+                    .leading
+                    .retain(|trivia| trivia.kind == TriviaKind::Blank);
+                existing.leading.extend(
+                    node.leading
+                        .into_iter()
+                        .filter(|trivia| trivia.kind != TriviaKind::Blank),
+                );
+            }
+            let both_maps = !existing.children.is_empty()
+                && !node.children.is_empty()
+                && Self::is_map_level(&existing.children)
+                && Self::is_map_level(&node.children);
+            if both_maps {
+                let indent = existing.children[0].indent;
+                Self::merge_nodes(&mut existing.children, node.children, indent);
+            } else {
+                existing.kind = node.kind;
+                existing.raw = node.raw;
+                existing.children = node.children;
+                existing.trailing = node.trailing;
+            }
+        }
+    }
+
+    fn is_map_level(nodes: &[CstNode]) -> bool {
+        !nodes
+            .iter()
+            .any(|node| matches!(node.kind, CstNodeKind::Item { .. }))
+    }
+
+    /// Moves `node` and everything below it so that `node` starts at column
+    /// `indent`, comments included.
+    fn reindent(node: &mut CstNode, indent: usize) {
+        let from = node.indent;
+        let mut pending = vec![node];
+        while let Some(node) = pending.pop() {
+            node.indent = (node.indent + indent).saturating_sub(from);
+            node.raw = format!("{}{}", " ".repeat(node.indent), node.raw.trim_start());
+            for trivia in &mut node.leading {
+                if let TriviaKind::Comment(_) = trivia.kind {
+                    trivia.raw = format!("{}{}", " ".repeat(node.indent), trivia.raw.trim_start());
+                }
+            }
+            for trivia in &mut node.trailing {
+                if let TriviaKind::Comment(_) = trivia.kind {
+                    let own = trivia.raw.len() - trivia.raw.trim_start().len();
+                    let shifted = (own + indent).saturating_sub(from);
+                    trivia.raw = format!("{}{}", " ".repeat(shifted), trivia.raw.trim_start());
+                }
+            }
+            pending.extend(node.children.iter_mut());
+        }
+    }
+
+    /// Reproduces the original source text exactly.
+    pub fn to_source(&self) -> String {
+        let mut lines = Vec::new();
+        for node in &self.nodes {
+            Self::collect_source(node, &mut lines);
+        }
+        lines.extend(self.trailing.iter().map(|trivia| trivia.raw.as_str()));
+
+        let mut source = lines.join("\n");
+        if self.final_newline {
+            source.push('\n');
+        }
+        source
+    }
+
+    fn collect_source<'a>(node: &'a CstNode, lines: &mut Vec<&'a str>) {
+        lines.extend(node.leading.iter().map(|trivia| trivia.raw.as_str()));
+        lines.push(&node.raw);
+        for child in &node.children {
+            Self::collect_source(child, lines);
+        }
+        lines.extend(node.trailing.iter().map(|trivia| trivia.raw.as_str()));
+    }
+
+    /// Renders the document in the canonical style: two spaces of indentation
+    /// per nesting level, a single space after `:`, `-` and `#`, and runs of
+    /// blank lines collapsed into one. Comments, quoting and values are kept.
+    pub fn format(&self) -> String {
+        self.format_inner(None, 2)
+    }
+
+    /// Like [`BellandeDocument::format`], but also rewrites string values so
+    /// escapes follow `options`. Each string keeps its original quote style
+    /// (see [`CstScalar::quote`]) unless its content forces double quotes.
+    /// Numbers, flow collections and comments are kept as written.
+    pub fn format_with_options(&self, options: &WriteOptions) -> String {
+        self.format_inner(Some(options), 2)
+    }
+
+    fn format_inner(&self, options: Option<&WriteOptions>, width: usize) -> String {
+        let mut out = String::new();
+        let own_shebang = self
+            .nodes
+            .first()
+            .map_or(&self.trailing, |node| &node.leading)
+            .iter()
+            .find_map(|trivia| match &trivia.kind {
+                TriviaKind::Shebang(line) => Some(line),
+                _ => None,
+            });
+        if let Some(shebang) =
+            own_shebang.or_else(|| options.and_then(|options| options.shebang.as_ref()))
+        {
+            out.push_str(shebang);
+            out.push('\n');
+        }
+        let starts_with_marker = self
+            .nodes
+            .first()
+            .is_some_and(|node| matches!(&node.kind, CstNodeKind::Text(text) if text == "---"));
+        if options.is_some_and(|options| options.document_start) && !starts_with_marker {
+            out.push_str("---\n");
+        }
+        for node in &self.nodes {
+            Self::format_node(node, 0, width, options, &mut out);
+        }
+        Self::format_trivia(&self.trailing, 0, &mut out);
+
+        while out.ends_with("\n\n") {
+            out.pop();
+        }
+        out
+    }
+
+    fn format_node(
+        node: &CstNode,
+        column: usize,
+        width: usize,
+        options: Option<&WriteOptions>,
+        out: &mut String,
+    ) {
+        Self::format_trivia(&node.leading, column, out);
+
+        out.push_str(&" ".repeat(column));
+        match &node.kind {
+            CstNodeKind::Entry { key, value } => {
+                out.push_str(key);
+                out.push(':');
+                if let Some(value) = value {
+                    out.push(' ');
+                    out.push_str(&Self::format_scalar(value, false, options));
+                }
+            }
+            CstNodeKind::Item { value } => {
+                out.push('-');
+                if let Some(value) = value {
+                    out.push(' ');
+                    out.push_str(&Self::format_scalar(value, true, options));
+                }
+            }
+            CstNodeKind::Text(text) => out.push_str(text),
+        }
+        out.push('\n');
+
+        // Children of an item continue its inline map, so they line up with
+        // the text after `- ` rather than a full level deeper.
+        let child_column = match node.kind {
+            CstNodeKind::Item { .. } => column + 2,
+            _ => column + width,
+        };
+        for child in &node.children {
+            Self::format_node(child, child_column, width, options, out);
+        }
+        Self::format_trivia(&node.trailing, child_column, out);
+    }
+
+    fn format_scalar(value: &CstScalar, is_item: bool, options: Option<&WriteOptions>) -> String {
+        let Some(options) = options else {
+            return value.raw.clone();
+        };
+        let format = BellandeFormat;
+        let parse_options = ParseOptions::default();
+        let scalar = format.strip_inline_comment(&value.raw, &parse_options);
+        let comment = value.raw[scalar.len()..].trim_start();
+        if value.quote == QuoteStyle::Single
+            || format.is_comment(scalar, &parse_options)
+            || scalar.starts_with(['{', '['])
+            || (is_item && format.find_item_key(scalar, ':').is_some())
+        {
+            return value.raw.clone();
+        }
+        let formatted = match format.resolve_scalar(scalar, &parse_options) {
+            Ok(BellandeValue::String(s)) if value.quote == QuoteStyle::Double => {
+                format.quote(&s, options)
+            }
+            Ok(resolved @ BellandeValue::String(_)) => format.format_value(&resolved, options),
+            _ => return value.raw.clone(),
+        };
+        if comment.is_empty() {
+            formatted
+        } else {
+            format!("{} {}", formatted, comment)
+        }
+    }
+
+    fn format_trivia(trivia: &[Trivia], column: usize, out: &mut String) {
+        for item in trivia {
+            match &item.kind {
+                TriviaKind::Blank => {
+                    if !out.is_empty() && !out.ends_with("\n\n") {
+                        out.push('\n');
+                    }
+                }
+                TriviaKind::Comment(text) => {
+                    out.push_str(&" ".repeat(column));
+                    out.push('#');
+                    let text = text.trim_end();
+                    if !text.is_empty() && !text.starts_with(['#', '!', ' ']) {
+                        out.push(' ');
+                    }
+                    out.push_str(text);
+                    out.push('\n');
+                }
+                // Written first by `format_inner`, ahead of any `---` marker.
+                TriviaKind::Shebang(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl BellandeFormat {
+    /// Parses a gzip-compressed file such as `config.bellande.gz`.
+    pub fn parse_bellande_gz<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+    ) -> Result<BellandeValue, BellandeError> {
+        self.parse_bellande_gz_with_options(file_path, &ParseOptions::default())
+    }
+
+    /// Like [`BellandeFormat::parse_bellande_gz`]. `max_file_bytes` limits
+    /// the decompressed size, so a small archive cannot expand without bound.
+    pub fn parse_bellande_gz_with_options<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        options: &ParseOptions,
+    ) -> Result<BellandeValue, BellandeError> {
+        let file_limit = options.max_file_bytes.unwrap_or(u64::MAX);
+        let decoder = flate2::read::GzDecoder::new(fs::File::open(&file_path)?);
+        let mut content = Vec::new();
+        decoder
+            .take(file_limit.saturating_add(1))
+            .read_to_end(&mut content)?;
+        if content.len() as u64 > file_limit {
+            return Err(BellandeError::LimitExceeded(format!(
+                "{} decompresses to more than {} bytes",
+                file_path.as_ref().display(),
+                file_limit
+            )));
+        }
+        self.parse_bellande_bytes_with_options(&content, options)
+    }
+
+    /// Writes `data` gzip-compressed with the default compression level.
+    pub fn write_bellande_gz<P: AsRef<Path>>(
+        &self,
+        data: &BellandeValue,
+        file_path: P,
+    ) -> Result<(), std::io::Error> {
+        let content = self.to_bellande_string(data, 0, &WriteOptions::default());
+        let mut encoder = flate2::write::GzEncoder::new(
+            fs::File::create(file_path)?,
+            flate2::Compression::default(),
+        );
+        encoder.write_all(content.as_bytes())?;
+        encoder.finish()?;
+        Ok(())
+    }
+}
+
+/// Lets `.bellande` files be layered with the `config` crate, as in
+/// `config::File::with_name("app").format(BellandeFormat)`.
+#[cfg(feature = "config")]
+impl config::Format for BellandeFormat {
+    fn parse(
+        &self,
+        uri: Option<&String>,
+        text: &str,
+    ) -> Result<config::Map<String, config::Value>, Box<dyn std::error::Error + Send + Sync>> {
+        match Self::to_config_value(uri, self.parse_bellande_str(text)?).kind {
+            config::ValueKind::Table(table) => Ok(table),
+            _ => Err("a config source must be a map at the top level".into()),
+        }
+    }
+}
+
+#[cfg(feature = "config")]
+impl config::FileStoredFormat for BellandeFormat {
+    fn file_extensions(&self) -> &'static [&'static str] {
+        &["bellande"]
+    }
+}
+
+#[cfg(feature = "config")]
+impl BellandeFormat {
+    fn to_config_value(uri: Option<&String>, value: BellandeValue) -> config::Value {
+        let kind = match value {
+            BellandeValue::String(s) | BellandeValue::Raw(s) => config::ValueKind::String(s),
+            BellandeValue::Integer(i) => config::ValueKind::I64(i),
+            BellandeValue::Float(f) => config::ValueKind::Float(f),
+            BellandeValue::Boolean(b) => config::ValueKind::Boolean(b),
+            BellandeValue::Null => config::ValueKind::Nil,
+            BellandeValue::List(list) => config::ValueKind::Array(
+                list.into_iter()
+                    .map(|item| Self::to_config_value(uri, item))
+                    .collect(),
+            ),
+            BellandeValue::Map(map) => config::ValueKind::Table(
+                map.into_iter()
+                    .map(|(key, value)| (key, Self::to_config_value(uri, value)))
+                    .collect(),
+            ),
+        };
+        config::Value::new(uri, kind)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl BellandeValue {
+    /// Reads a string as a point in time. Accepted forms are RFC 3339 with
+    /// an offset (`2024-05-01T12:30:00Z`, `2024-05-01T12:30:00.5+02:00`),
+    /// the same without an offset using `T` or a space
+    /// (`2024-05-01 12:30:00`), and a bare date (`2024-05-01`, at midnight).
+    /// Forms without an offset are taken as UTC. Anything else, including a
+    /// non-string value, gives `None`.
+    pub fn as_datetime(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        let s = self.as_str()?.trim();
+        if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(s) {
+            return Some(datetime);
+        }
+        let naive = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+            .iter()
+            .find_map(|format| chrono::NaiveDateTime::parse_from_str(s, format).ok())
+            .or_else(|| {
+                chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .ok()?
+                    .and_hms_opt(0, 0, 0)
+            })?;
+        Some(naive.and_utc().fixed_offset())
+    }
+
+    /// Reads a string as a duration in the `humantime` grammar: one or more
+    /// numbers with a unit, optionally separated by spaces, such as `30s`,
+    /// `1h 30m`, `2days` or `150ms`. Units range from `ns` to `y`. A bare
+    /// number has no unit and gives `None`, as does a non-string value.
+    pub fn as_duration(&self) -> Option<std::time::Duration> {
+        humantime::parse_duration(self.as_str()?.trim()).ok()
+    }
+}
+
+#[cfg(feature = "toml")]
+impl BellandeValue {
+    /// Serializes the value as a TOML document. TOML has no null, so `Null`
+    /// entries are left out, and a non-map root is written under a `value`
+    /// key because a TOML document is always a table.
+    pub fn to_toml(&self) -> String {
+        let table = match Self::to_toml_value(self) {
+            Some(toml::Value::Table(table)) => table,
+            Some(other) => {
+                let mut table = toml::Table::new();
+                table.insert("value".to_string(), other);
+                table
+            }
+            None => toml::Table::new(),
+        };
+        toml::to_string(&table).expect("TOML tables always serialize")
+    }
+
+    /// Parses a TOML document. Tables become maps, arrays become lists and
+    /// datetimes are kept as their RFC 3339 string form.
+    pub fn from_toml(content: &str) -> Result<BellandeValue, BellandeError> {
+        let table = content.parse::<toml::Table>().map_err(|err| {
+            let offset = err.span().map_or(0, |span| span.start);
+            let before = &content[..offset.min(content.len())];
+            let line = before.matches('\n').count() + 1;
+            let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+            BellandeError::parse(line, column, err.message())
+        })?;
+        Ok(Self::from_toml_value(toml::Value::Table(table)))
+    }
+
+    fn to_toml_value(value: &BellandeValue) -> Option<toml::Value> {
+        Some(match value {
+            BellandeValue::String(s) | BellandeValue::Raw(s) => toml::Value::String(s.clone()),
+            BellandeValue::Integer(i) => toml::Value::Integer(*i),
+            BellandeValue::Float(f) => toml::Value::Float(*f),
+            BellandeValue::Boolean(b) => toml::Value::Boolean(*b),
+            BellandeValue::Null => return None,
+            BellandeValue::List(list) => {
+                toml::Value::Array(list.iter().filter_map(Self::to_toml_value).collect())
+            }
+            BellandeValue::Map(map) => toml::Value::Table(
+                map.iter()
+                    .filter_map(|(key, value)| {
+                        Self::to_toml_value(value).map(|value| (key.clone(), value))
+                    })
+                    .collect(),
+            ),
+        })
+    }
+
+    fn from_toml_value(value: toml::Value) -> BellandeValue {
+        match value {
+            toml::Value::String(s) => BellandeValue::String(s),
+            toml::Value::Integer(i) => BellandeValue::Integer(i),
+            toml::Value::Float(f) => BellandeValue::Float(f),
+            toml::Value::Boolean(b) => BellandeValue::Boolean(b),
+            toml::Value::Datetime(datetime) => BellandeValue::String(datetime.to_string()),
+            toml::Value::Array(array) => {
+                BellandeValue::List(array.into_iter().map(Self::from_toml_value).collect())
+            }
+            toml::Value::Table(table) => BellandeValue::Map(
+                table
+                    .into_iter()
+                    .map(|(key, value)| (key, Self::from_toml_value(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl BellandeValue {
+    /// Serializes the value as a single YAML document. Every value has a
+    /// direct YAML counterpart; `Raw` blocks are written as strings, and no
+    /// tags or anchors are emitted.
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(&Self::to_yaml_value(self)).expect("YAML values always serialize")
+    }
+
+    /// Parses a single YAML document. Mappings become maps and sequences
+    /// lists. Aliases are expanded into copies of their anchored value and
+    /// `<<` merge keys are applied. Tags such as `!!str` or `!custom` are
+    /// dropped and the tagged value kept. Numeric, boolean and null keys are
+    /// read as their text, while a sequence or mapping used as a key fails
+    /// with a [`BellandeError::Validation`]. A stream of several `---`
+    /// documents is rejected as a [`BellandeError::Parse`]; split it first.
+    pub fn from_yaml(content: &str) -> Result<BellandeValue, BellandeError> {
+        let parse_error = |err: serde_yaml::Error| {
+            let (line, column) = err
+                .location()
+                .map_or((1, 1), |location| (location.line(), location.column()));
+            BellandeError::parse(line, column, err.to_string())
+        };
+        let mut value = serde_yaml::from_str::<serde_yaml::Value>(content).map_err(parse_error)?;
+        value.apply_merge().map_err(parse_error)?;
+        Self::from_yaml_value(value, "")
+    }
+
+    fn to_yaml_value(value: &BellandeValue) -> serde_yaml::Value {
+        match value {
+            BellandeValue::String(s) | BellandeValue::Raw(s) => {
+                serde_yaml::Value::String(s.clone())
+            }
+            BellandeValue::Integer(i) => serde_yaml::Value::Number((*i).into()),
+            BellandeValue::Float(f) => serde_yaml::Value::Number((*f).into()),
+            BellandeValue::Boolean(b) => serde_yaml::Value::Bool(*b),
+            BellandeValue::Null => serde_yaml::Value::Null,
+            BellandeValue::List(list) => {
+                serde_yaml::Value::Sequence(list.iter().map(Self::to_yaml_value).collect())
+            }
+            BellandeValue::Map(map) => serde_yaml::Value::Mapping(
+                map.iter()
+                    .map(|(key, value)| {
+                        (
+                            serde_yaml::Value::String(key.clone()),
+                            Self::to_yaml_value(value),
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn from_yaml_value(
+        value: serde_yaml::Value,
+        path: &str,
+    ) -> Result<BellandeValue, BellandeError> {
+        Ok(match value {
+            serde_yaml::Value::Null => BellandeValue::Null,
+            serde_yaml::Value::Bool(b) => BellandeValue::Boolean(b),
+            serde_yaml::Value::Number(n) => match n.as_i64() {
+                Some(i) => BellandeValue::Integer(i),
+                None => BellandeValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_yaml::Value::String(s) => BellandeValue::String(s),
+            serde_yaml::Value::Sequence(items) => BellandeValue::List(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, item)| {
+                        Self::from_yaml_value(item, &Self::join_path(path, &index.to_string()))
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            serde_yaml::Value::Mapping(mapping) => {
+                let mut map = BTreeMap::new();
+                for (key, value) in mapping {
+                    let key = match key {
+                        serde_yaml::Value::String(s) => s,
+                        serde_yaml::Value::Number(n) => n.to_string(),
+                        serde_yaml::Value::Bool(b) => b.to_string(),
+                        serde_yaml::Value::Null => "null".to_string(),
+                        _ => {
+                            return Err(BellandeError::Validation {
+                                path: path.to_string(),
+                                message: "a YAML key must be a scalar".to_string(),
+                            })
+                        }
+                    };
+                    let value = Self::from_yaml_value(value, &Self::join_path(path, &key))?;
+                    map.insert(key, value);
+                }
+                BellandeValue::Map(map)
+            }
+            serde_yaml::Value::Tagged(tagged) => Self::from_yaml_value(tagged.value, path)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> BellandeValue {
+        BellandeFormat.parse_bellande_str(content).unwrap()
+    }
+
+    fn map(entries: Vec<(&str, BellandeValue)>) -> BellandeValue {
+        BellandeValue::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn string(value: &str) -> BellandeValue {
+        BellandeValue::String(value.to_string())
+    }
+
+    fn round_trip(value: &BellandeValue) -> BellandeValue {
+        let formatter = BellandeFormat;
+        parse(&formatter.to_bellande_string(value, 0, &WriteOptions::default()))
+    }
+
+    #[cfg(feature = "std")]
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("bellande_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn infers_scalar_types() {
+        let parsed = parse("int: 42\nneg: -7\nfloat: 3.5\ntext: hello world\n");
+        assert_eq!(
+            parsed,
+            map(vec![
+                ("int", BellandeValue::Integer(42)),
+                ("neg", BellandeValue::Integer(-7)),
+                ("float", BellandeValue::Float(3.5)),
+                ("text", string("hello world")),
+            ])
+        );
+    }
+
+    #[test]
+    fn types_single_values_like_the_parser() {
+        let format = BellandeFormat;
+        assert_eq!(format.parse_scalar(" 42 "), BellandeValue::Integer(42));
+        assert_eq!(format.parse_scalar("1e3"), BellandeValue::Float(1000.0));
+        assert_eq!(format.parse_scalar("TRUE"), BellandeValue::Boolean(true));
+        assert_eq!(format.parse_scalar("null"), BellandeValue::Null);
+        assert_eq!(format.parse_scalar("\"42\""), string("42"));
+        assert_eq!(format.parse_scalar("v1.2"), string("v1.2"));
+    }
+
+    #[test]
+    fn parses_booleans_and_null_case_insensitively() {
+        let parsed = parse("a: true\nb: FALSE\nc: Null\n");
+        assert_eq!(
+            parsed,
+            map(vec![
+                ("a", BellandeValue::Boolean(true)),
+                ("b", BellandeValue::Boolean(false)),
+                ("c", BellandeValue::Null),
+            ])
+        );
+    }
+
+    #[test]
+    fn quoted_values_stay_strings() {
+        let parsed = parse("a: \"42\"\nb: \"true\"\nc: \"C++\"\n");
+        assert_eq!(
+            parsed,
+            map(vec![
+                ("a", string("42")),
+                ("b", string("true")),
+                ("c", string("C++"))
+            ])
+        );
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let parsed = parse("# header\n\nkey: value\n   # indented comment\n\n");
+        assert_eq!(parsed, map(vec![("key", string("value"))]));
+    }
+
+    #[test]
+    fn strips_inline_comments_outside_quotes() {
+        let parsed =
+            parse("port: 80 # default\ncolor: #fff\nquote: \"a # b\"\nlist:\n  - x # first\n");
+        assert_eq!(
+            parsed,
+            map(vec![
+                ("port", BellandeValue::Integer(80)),
+                ("color", string("#fff")),
+                ("quote", string("a # b")),
+                ("list", BellandeValue::List(vec![string("x")])),
+            ])
+        );
+    }
+
+    #[test]
+    fn supports_custom_comment_prefixes() {
+        let content = "; ini comment\n// c comment\n# not a comment: here\nport: 80 ; default\n";
+        let options = ParseOptions {
+            comment_prefixes: vec![";".to_string(), "//".to_string()],
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            BellandeFormat
+                .parse_bellande_str_with_options(content, &options)
+                .unwrap(),
+            map(vec![
+                ("# not a comment", string("here")),
+                ("port", BellandeValue::Integer(80)),
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_lists() {
+        let parsed = parse("skills:\n    - Python\n    - 3\n    - \"C++\"\nempty:\n");
+        assert_eq!(
+            parsed,
+            map(vec![
+                (
+                    "skills",
+                    BellandeValue::List(vec![
+                        string("Python"),
+                        BellandeValue::Integer(3),
+                        string("C++"),
+                    ])
+                ),
+                ("empty", BellandeValue::List(Vec::new())),
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_nested_maps() {
+        let parsed = parse("user:\n    name: John Doe\n    tags:\n        - a\nversion: 1.0\n");
+        assert_eq!(
+            parsed,
+            map(vec![
+                (
+                    "user",
+                    map(vec![
+                        ("name", string("John Doe")),
+                        ("tags", BellandeValue::List(vec![string("a")])),
+                    ])
+                ),
+                ("version", BellandeValue::Float(1.0)),
+            ])
+        );
+    }
+
+    #[test]
+    fn expands_dotted_keys_when_enabled() {
+        let content = "server.port: 8080\nserver.host: localhost\n";
+        let options = ParseOptions {
+            expand_dotted_keys: true,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            BellandeFormat
+                .parse_bellande_str_with_options(content, &options)
+                .unwrap(),
+            map(vec![(
+                "server",
+                map(vec![
+                    ("port", BellandeValue::Integer(8080)),
+                    ("host", string("localhost")),
+                ])
+            )])
+        );
+        assert_eq!(
+            parse(content),
+            map(vec![
+                ("server.port", BellandeValue::Integer(8080)),
+                ("server.host", string("localhost")),
+            ])
+        );
+    }
+
+    #[test]
+    fn round_trips_simple_documents() {
+        let value = map(vec![
+            ("name", string("John Doe")),
+            ("age", BellandeValue::Integer(30)),
+            ("ratio", BellandeValue::Float(0.25)),
+            ("active", BellandeValue::Boolean(true)),
+            ("missing", BellandeValue::Null),
+            (
+                "skills",
+                BellandeValue::List(vec![string("Python"), BellandeValue::Integer(2)]),
+            ),
+            ("user", map(vec![("city", string("Anytown"))])),
+        ]);
+        assert_eq!(round_trip(&value), value);
+    }
+
+    #[test]
+    fn round_trips_strings_that_look_like_other_types() {
+        let value = map(vec![
+            ("number", string("123")),
+            ("float", string("1.5")),
+            ("boolean", string("false")),
+            ("empty", string("")),
+            ("whole", BellandeValue::Float(2.0)),
+            ("int_tag", string("!int")),
+            ("bool_tag", string("!bool")),
+            ("float_tag", string("!float")),
+            ("null_tag", string("!null")),
+            ("str_tag", string("!str")),
+        ]);
+        assert_eq!(round_trip(&value), value);
+        assert!(BellandeFormat::verify_roundtrip("a: \"!null\"\n").is_ok());
+    }
+
+    #[test]
+    fn round_trips_strings_that_look_like_list_items() {
+        let value = map(vec![(
+            "items",
+            BellandeValue::List(vec![string("-"), string("- x"), string("-x")]),
+        )]);
+        assert_eq!(round_trip(&value), value);
+        assert_eq!(round_trip(&string("-")), string("-"));
+        assert!(BellandeFormat::verify_roundtrip("a: [\"-\"]\n").is_ok());
+    }
+
+    #[test]
+    fn round_trips_a_document_marker_string() {
+        assert_eq!(round_trip(&string("---")), string("---"));
+        assert!(BellandeFormat::verify_roundtrip("\"---\"\n").is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn writes_and_reads_files() {
+        let path = temp_path("write.bellande");
+        let value = map(vec![("key", string("value"))]);
+        BellandeFormat.write_bellande(&value, &path).unwrap();
+        assert_eq!(BellandeFormat.parse_bellande(&path).unwrap(), value);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn merge_combines_maps_and_replaces_scalars() {
+        let mut base = parse("a:\n  x: 1\n  y: 2\nb: 1\n");
+        base.merge(parse("a:\n  y: 3\nb: 2\nc: 3\n"));
+        assert_eq!(base, parse("a:\n  x: 1\n  y: 3\nb: 2\nc: 3\n"));
+    }
+
+    #[test]
+    fn merges_lists_by_strategy() {
+        let base = parse("ports:\n  - 80\n  - 443\nservices:\n  - name: api\n    port: 1\n  - name: web\n    port: 2\n");
+        let overlay = parse(
+            "ports:\n  - 8080\nservices:\n  - name: web\n    port: 3\n  - name: db\n  - legacy\n",
+        );
+        let merged = |lists: ListStrategy| {
+            let mut merged = base.clone();
+            merged.merge_with(overlay.clone(), &lists);
+            merged
+        };
+
+        assert_eq!(merged(ListStrategy::Replace), overlay);
+        assert_eq!(
+            merged(ListStrategy::Concat),
+            parse("ports:\n  - 80\n  - 443\n  - 8080\nservices:\n  - name: api\n    port: 1\n  - name: web\n    port: 2\n  - name: web\n    port: 3\n  - name: db\n  - legacy\n")
+        );
+        assert_eq!(
+            merged(ListStrategy::MergeByIndex),
+            parse("ports:\n  - 8080\n  - 443\nservices:\n  - name: web\n    port: 3\n  - name: db\n    port: 2\n  - legacy\n")
+        );
+        assert_eq!(
+            merged(ListStrategy::MergeByKey("name".to_string())),
+            parse("ports:\n  - 80\n  - 443\n  - 8080\nservices:\n  - name: api\n    port: 1\n  - name: web\n    port: 3\n  - name: db\n  - legacy\n")
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn parses_directory_in_file_name_order() {
+        let dir = temp_path("conf.d");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("20-override.bellande"), "port: 9090\n").unwrap();
+        fs::write(dir.join("10-base.bellande"), "port: 80\nhost: a\n").unwrap();
+        fs::write(dir.join("notes.txt"), "port: 1\n").unwrap();
+
+        let merged = BellandeFormat.parse_bellande_dir(&dir).unwrap();
+        assert_eq!(
+            merged,
+            map(vec![
+                ("port", BellandeValue::Integer(9090)),
+                ("host", string("a"))
+            ])
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn document_round_trips_source_and_formats() {
+        let source = "# header\n\n\nuser:\n    name:   John\n    #note\n    age: 30\nskills:\n    - \"C++\"\n";
+        let document = BellandeFormat.parse_document(source);
+        assert_eq!(document.to_source(), source);
+        assert_eq!(
+            document.format(),
+            "# header\n\nuser:\n  name: John\n  # note\n  age: 30\nskills:\n  - \"C++\"\n"
+        );
+    }
+
+    #[test]
+    fn reports_line_and_column_of_malformed_lines() {
+        let source = "user:\n  name: a\n  oops\n";
+        let err = BellandeFormat.parse_bellande_str(source).unwrap_err();
+        assert!(matches!(
+            err,
+            BellandeError::Parse {
+                line: 3,
+                column: 3,
+                ..
+            }
+        ));
+        assert_eq!(
+            BellandeFormat.format_error(source, &err),
+            "error: expected `key: value` or `- item`\n --> line 3, column 3\n  |\n3 |   oops\n  |   ^"
+        );
+    }
+
+    #[test]
+    fn rejects_list_items_outside_lists() {
+        let err = BellandeFormat
+            .parse_bellande_str("a: 1\n- a\n")
+            .unwrap_err();
+        assert!(matches!(err, BellandeError::Parse { line: 2, .. }));
+    }
+
+    #[test]
+    fn parses_lists_mixing_scalars_and_maps() {
+        let parsed = parse(
+            "projects:\n  - name: Project A\n    status: done\n    tags:\n      - x\n  - legacy\n  - name: Project B\n",
+        );
+        assert_eq!(
+            parsed,
+            map(vec![(
+                "projects",
+                BellandeValue::List(vec![
+                    map(vec![
+                        ("name", string("Project A")),
+                        ("status", string("done")),
+                        ("tags", BellandeValue::List(vec![string("x")])),
+                    ]),
+                    string("legacy"),
+                    map(vec![("name", string("Project B"))]),
+                ])
+            )])
+        );
+        assert_eq!(round_trip(&parsed), parsed);
+    }
+
+    #[test]
+    fn list_items_with_colons_but_no_key_stay_scalars() {
+        let parsed = parse("urls:\n  - http://example.com\n  - \"a: b\"\n");
+        assert_eq!(
+            parsed,
+            map(vec![(
+                "urls",
+                BellandeValue::List(vec![string("http://example.com"), string("a: b")])
+            )])
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn converts_to_and_from_toml() {
+        let value = parse("name: demo\nport: 8080\nserver:\n  tls: true\n");
+        let toml = value.to_toml();
+        assert_eq!(BellandeValue::from_toml(&toml).unwrap(), value);
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn converts_to_and_from_yaml() {
+        let value = parse("name: demo\nport: 8080\nratio: 0.5\nlog: null\nserver:\n  tls: true\n  hosts:\n    - a\n    - b\n");
+        assert_eq!(BellandeValue::from_yaml(&value.to_yaml()).unwrap(), value);
+
+        let yaml =
+            "base: &base\n  port: 80\nprod:\n  <<: *base\n  host: !custom example.com\n1: one\n";
+        assert_eq!(
+            BellandeValue::from_yaml(yaml).unwrap(),
+            parse("base:\n  port: 80\nprod:\n  port: 80\n  host: example.com\n1: one\n")
+        );
+        assert!(matches!(
+            BellandeValue::from_yaml("? [a, b]\n: c\n"),
+            Err(BellandeError::Validation { .. })
+        ));
+        assert!(matches!(
+            BellandeValue::from_yaml("a: 1\n---\nb: 2\n"),
+            Err(BellandeError::Parse { .. })
+        ));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn reads_datetimes_and_durations() {
+        let value = parse(
+            "at: 2024-05-01T12:30:00+02:00\nnaive: 2024-05-01 10:30:00\nday: 2024-05-01\nttl: 1h 30m\nport: 80\n",
+        );
+        let at = value.get_path("at").unwrap().as_datetime().unwrap();
+        assert_eq!(at.to_rfc3339(), "2024-05-01T12:30:00+02:00");
+        let naive = value.get_path("naive").unwrap().as_datetime().unwrap();
+        assert_eq!(naive.to_rfc3339(), "2024-05-01T10:30:00+00:00");
+        let day = value.get_path("day").unwrap().as_datetime().unwrap();
+        assert_eq!(day.to_rfc3339(), "2024-05-01T00:00:00+00:00");
+        assert_eq!(
+            value.get_path("ttl").unwrap().as_duration(),
+            Some(std::time::Duration::from_secs(5400))
+        );
+        assert_eq!(value.get_path("port").unwrap().as_duration(), None);
+        assert_eq!(value.get_path("port").unwrap().as_datetime(), None);
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn loads_through_the_config_crate() {
+        let settings = config::Config::builder()
+            .add_source(config::File::from_str(
+                "name: demo\nserver:\n  port: 8080\n",
+                BellandeFormat,
+            ))
+            .build()
+            .unwrap();
+        assert_eq!(settings.get_string("name").unwrap(), "demo");
+        assert_eq!(settings.get_int("server.port").unwrap(), 8080);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn reads_and_writes_gzip_files() {
+        let value = parse("name: demo\nport: 8080\nserver:\n  tls: true\n");
+        let path = temp_path("config.bellande.gz");
+        BellandeFormat.write_bellande_gz(&value, &path).unwrap();
+        assert_eq!(fs::read(&path).unwrap()[..2], [0x1f, 0x8b]);
+        assert_eq!(BellandeFormat.parse_bellande_gz(&path).unwrap(), value);
+
+        let options = ParseOptions {
+            max_file_bytes: Some(8),
+            ..ParseOptions::default()
+        };
+        assert!(matches!(
+            BellandeFormat.parse_bellande_gz_with_options(&path, &options),
+            Err(BellandeError::LimitExceeded(_))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn rejects_files_over_max_file_bytes() {
+        let path = temp_path("limit.bellande");
+        fs::write(&path, "key: a fairly long value\n").unwrap();
+        let options = ParseOptions {
+            max_file_bytes: Some(8),
+            ..ParseOptions::default()
+        };
+        let err = BellandeFormat
+            .parse_bellande_with_options(&path, &options)
+            .unwrap_err();
+        assert!(matches!(err, BellandeError::LimitExceeded(_)));
+
+        let options = ParseOptions {
+            max_file_bytes: Some(1024),
+            ..ParseOptions::default()
+        };
+        assert!(BellandeFormat
+            .parse_bellande_with_options(&path, &options)
+            .is_ok());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn resolves_json_pointers() {
+        let value =
+            parse("server:\n  port: 8080\n  a/b: slash\n  m~n: tilde\nhosts:\n  - a\n  - b\n");
+        assert_eq!(value.pointer(""), Some(&value));
+        assert_eq!(
+            value.pointer("/server/port"),
+            Some(&BellandeValue::Integer(8080))
+        );
+        assert_eq!(value.pointer("/server/a~1b"), Some(&string("slash")));
+        assert_eq!(value.pointer("/server/m~0n"), Some(&string("tilde")));
+        assert_eq!(value.pointer("/hosts/1"), Some(&string("b")));
+        assert_eq!(value.pointer("/hosts/01"), None);
+        assert_eq!(value.pointer("/hosts/2"), None);
+        assert_eq!(value.pointer("server"), None);
+    }
+
+    #[test]
+    fn gets_values_by_dotted_path() {
+        let value = parse("server:\n  port: 8080\nprojects:\n  - name: a\n");
+        assert_eq!(
+            value.get_path("server.port"),
+            Some(&BellandeValue::Integer(8080))
+        );
+        assert_eq!(value.get_path("projects.0.name"), Some(&string("a")));
+        assert_eq!(value.get_path("projects.1"), None);
+        assert_eq!(value.get_path("server.port.x"), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn writes_a_single_subtree() {
+        let value = parse("database:\n  host: db\n  port: 5432\nserver:\n  port: 80\n");
+        let path = temp_path("subtree.bellande");
+        value.write_subtree("database", &path).unwrap();
+        assert_eq!(
+            BellandeFormat.parse_bellande(&path).unwrap(),
+            map(vec![
+                ("host", string("db")),
+                ("port", BellandeValue::Integer(5432)),
+            ])
+        );
+        assert!(matches!(
+            value.write_subtree("missing", &path),
+            Err(BellandeError::MissingPath(_))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reports_trailing_content_when_asked() {
+        let content = "name: John Doe\nquoted: \"a\" b\nok: \"a b\"\n";
+        assert!(BellandeFormat.parse_bellande_str(content).is_ok());
+
+        let options = ParseOptions {
+            trailing_content: TrailingContent::Warn,
+            ..ParseOptions::default()
+        };
+        let (value, warnings) = BellandeFormat
+            .parse_bellande_str_with_warnings(content, &options)
+            .unwrap();
+        assert_eq!(value.get_path("name"), Some(&string("John Doe")));
+        assert_eq!(
+            warnings.iter().map(|w| w.line).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        let options = ParseOptions {
+            trailing_content: TrailingContent::Reject,
+            ..ParseOptions::default()
+        };
+        assert!(matches!(
+            BellandeFormat.parse_bellande_str_with_options(content, &options),
+            Err(BellandeError::Parse { line: 1, .. })
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn writes_atomically_over_existing_files() {
+        let path = temp_path("atomic.bellande");
+        fs::write(&path, "old: true\n").unwrap();
+        let value = map(vec![("new", BellandeValue::Boolean(true))]);
+        BellandeFormat.write_bellande_atomic(&value, &path).unwrap();
+        assert_eq!(BellandeFormat.parse_bellande(&path).unwrap(), value);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn writes_atomically_from_several_threads() {
+        let path = temp_path("atomic_concurrent.bellande");
+        fs::write(&path, "old: true\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+
+        let values: Vec<BellandeValue> = (0..8)
+            .map(|i| map(vec![("writer", BellandeValue::Integer(i))]))
+            .collect();
+        std::thread::scope(|scope| {
+            for value in &values {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        BellandeFormat.write_bellande_atomic(value, path).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert!(values.contains(&BellandeFormat.parse_bellande(&path).unwrap()));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let prefix = format!(".{}.", path.file_name().unwrap().to_string_lossy());
+        let leftovers = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(&prefix)
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn accessors_borrow_or_take_ownership() {
+        let value = parse("name: demo\nport: 80\nratio: 0.5\non: true\nhosts:\n  - a\n");
+        assert_eq!(
+            value.get_path("name").and_then(BellandeValue::as_str),
+            Some("demo")
+        );
+        assert_eq!(
+            value.get_path("port").and_then(BellandeValue::as_f64),
+            Some(80.0)
+        );
+        assert_eq!(
+            value.get_path("ratio").and_then(BellandeValue::as_i64),
+            None
+        );
+        assert_eq!(
+            value.get_path("on").and_then(BellandeValue::as_bool),
+            Some(true)
+        );
+
+        let mut map = value.into_map().unwrap();
+        let hosts = map.remove("hosts").unwrap().into_list().unwrap();
+        assert_eq!(hosts, vec![string("a")]);
+        assert_eq!(
+            map.remove("name").unwrap().into_string(),
+            Some("demo".to_string())
+        );
+        assert_eq!(map.remove("port").unwrap().into_string(), None);
+    }
+
+    #[test]
+    fn schema_rejects_values_outside_the_allowed_set() {
+        let mut schema = BellandeSchema::default();
+        schema.fields.insert(
+            "log_level".to_string(),
+            FieldSchema {
+                required: true,
+                allowed_values: Some(
+                    ["debug", "info", "warn", "error"]
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect(),
+                ),
+            },
+        );
+        schema.fields.insert(
+            "name".to_string(),
+            FieldSchema {
+                required: true,
+                ..FieldSchema::default()
+            },
+        );
+
+        assert!(schema
+            .validate(&parse("log_level: warn\nname: a\n"))
+            .is_ok());
+
+        let errors = schema.validate(&parse("log_level: verbose\n")).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`log_level`: expected one of debug, info, warn, error, found `verbose`",
+                "`name`: required field is missing",
+            ]
+        );
+    }
+
+    #[test]
+    fn removes_keys_at_any_depth() {
+        let value = parse(
+            "debug: true\nserver:\n  port: 80\n  debug: 1\nusers:\n  - name: a\n    password: x\n",
+        );
+        assert_eq!(
+            value.without_keys(&["debug", "password"]),
+            parse("server:\n  port: 80\nusers:\n  - name: a\n")
+        );
+    }
+
+    #[test]
+    fn parses_delimited_blocks_regardless_of_indentation() {
+        let parsed = parse(
+            "server: {\nhost: localhost\n      port: 8080,\n  tls: {enabled: true}\n}\nhosts: [\n  a, b\n      \"c, d\" # comment\n]\ninline: [1, 2.5, null]\nafter: 1\n",
+        );
+        assert_eq!(
+            parsed,
+            map(vec![
+                (
+                    "server",
+                    map(vec![
+                        ("host", string("localhost")),
+                        ("port", BellandeValue::Integer(8080)),
+                        ("tls", map(vec![("enabled", BellandeValue::Boolean(true))])),
+                    ])
+                ),
+                (
+                    "hosts",
+                    BellandeValue::List(vec![string("a"), string("b"), string("c, d")])
+                ),
+                (
+                    "inline",
+                    BellandeValue::List(vec![
+                        BellandeValue::Integer(1),
+                        BellandeValue::Float(2.5),
+                        BellandeValue::Null,
+                    ])
+                ),
+                ("after", BellandeValue::Integer(1)),
+            ])
+        );
+    }
+
+    #[test]
+    fn nests_delimited_and_indented_blocks() {
+        let source = "server:\n  name: a\n  limits: {\n        cpu: 2,\n  memory: {\n soft: 1\n      hard: 2 }\n    tags: [x,\n y\n ,z]\n }\n  port: 80\nitems:\n  - {a: 1,\n  b: 2}\n  - [1,\n    # comment\n    2]\nafter: 1\n";
+        let expected = map(vec![
+            (
+                "server",
+                map(vec![
+                    ("name", string("a")),
+                    (
+                        "limits",
+                        map(vec![
+                            ("cpu", BellandeValue::Integer(2)),
+                            (
+                                "memory",
+                                map(vec![
+                                    ("soft", BellandeValue::Integer(1)),
+                                    ("hard", BellandeValue::Integer(2)),
+                                ]),
+                            ),
+                            (
+                                "tags",
+                                BellandeValue::List(vec![string("x"), string("y"), string("z")]),
+                            ),
+                        ]),
+                    ),
+                    ("port", BellandeValue::Integer(80)),
+                ]),
+            ),
+            (
+                "items",
+                BellandeValue::List(vec![
+                    map(vec![
+                        ("a", BellandeValue::Integer(1)),
+                        ("b", BellandeValue::Integer(2)),
+                    ]),
+                    BellandeValue::List(vec![BellandeValue::Integer(1), BellandeValue::Integer(2)]),
+                ]),
+            ),
+            ("after", BellandeValue::Integer(1)),
+        ]);
+        assert_eq!(parse(source), expected);
+
+        let document = BellandeFormat.parse_document(source);
+        assert_eq!(document.to_source(), source);
+        assert_eq!(parse(&document.format()), expected);
+        assert!(BellandeFormat
+            .parse_bellande_str("a: [1,\n,\n2]\n")
+            .is_err());
+    }
+
+    #[test]
+    fn reports_errors_inside_delimited_blocks() {
+        let err = BellandeFormat
+            .parse_bellande_str("a: {\n  b: 1\n  c 2\n}\n")
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            BellandeError::Parse {
+                line: 3,
+                column: 3,
+                ..
+            }
+        ));
+        assert!(BellandeFormat.parse_bellande_str("a: [1, 2\n").is_err());
+    }
+
+    #[test]
+    fn round_trips_empty_containers() {
+        let value = map(vec![
+            ("empty_map", map(vec![])),
+            ("empty_list", BellandeValue::List(vec![])),
+            ("bracketed", string("[WARN]")),
+            (
+                "items",
+                BellandeValue::List(vec![map(vec![]), BellandeValue::List(vec![])]),
+            ),
+        ]);
+        assert_eq!(round_trip(&value), value);
+
+        let formatter = BellandeFormat;
+        let options = WriteOptions::default();
+        assert_eq!(
+            formatter.to_bellande_string(&BellandeValue::List(vec![]), 0, &options),
+            "[]"
+        );
+        assert_eq!(
+            formatter.to_bellande_string(&map(vec![]), 0, &options),
+            "{}"
+        );
+        assert_eq!(
+            round_trip(&BellandeValue::List(vec![])),
+            BellandeValue::List(vec![])
+        );
+        assert!(BellandeFormat::verify_roundtrip("[]").is_ok());
+    }
+
+    #[test]
+    fn names_value_types() {
+        let value = parse("s: a\ni: 1\nf: 1.5\nb: true\nn: null\nl: []\nm: {}\n");
+        let names: BTreeMap<&str, &str> = value
+            .as_map()
+            .unwrap()
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.type_name()))
+            .collect();
+        assert_eq!(
+            names.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("b", "boolean"),
+                ("f", "float"),
+                ("i", "integer"),
+                ("l", "list"),
+                ("m", "map"),
+                ("n", "null"),
+                ("s", "string"),
+            ]
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn document_keeps_blank_lines_between_sections() {
+        let source = "server:\n    host: a\n    port: 1\n\n\n\ndatabase:\n    name: b\n\n# logging\nlog: info\n\n";
+        let document = BellandeFormat.parse_document(source);
+        let path = temp_path("sections.bellande");
+        BellandeFormat.write_document(&document, &path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "server:\n  host: a\n  port: 1\n\ndatabase:\n  name: b\n\n# logging\nlog: info\n"
+        );
+        assert_eq!(BellandeFormat.parse_bellande(&path).unwrap(), parse(source));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn preserves_leading_zeros_when_asked() {
+        let content = "id: 007\nphone: 0123456789\nzero: 0\nratio: 0.5\npadded: [00.5, -01]\n";
+        assert_eq!(
+            parse(content).get_path("id"),
+            Some(&BellandeValue::Integer(7))
+        );
+
+        let options = ParseOptions {
+            preserve_leading_zeros: true,
+            ..ParseOptions::default()
+        };
+        let parsed = BellandeFormat
+            .parse_bellande_str_with_options(content, &options)
+            .unwrap();
+        assert_eq!(
+            parsed,
+            map(vec![
+                ("id", string("007")),
+                ("phone", string("0123456789")),
+                ("zero", BellandeValue::Integer(0)),
+                ("ratio", BellandeValue::Float(0.5)),
+                (
+                    "padded",
+                    BellandeValue::List(vec![string("00.5"), string("-01")])
+                ),
+            ])
+        );
+        assert_eq!(round_trip(&parsed), parsed);
+    }
+
+    #[test]
+    fn reads_and_writes_percentages() {
+        let value = parse("cpu_limit: 80%\nshare: 12.5 %\nplain: 80\nbad: abc%\n");
+        assert_eq!(
+            value.get_path("cpu_limit").unwrap().as_percent(),
+            Some(80.0)
+        );
+        assert_eq!(value.get_path("share").unwrap().as_percent(), Some(12.5));
+        assert_eq!(value.get_path("plain").unwrap().as_percent(), None);
+        assert_eq!(value.get_path("bad").unwrap().as_percent(), None);
+
+        let written = map(vec![("limit", BellandeValue::percent(75.5))]);
+        assert_eq!(
+            BellandeFormat.to_bellande_string(&written, 0, &WriteOptions::default()),
+            "limit: 75.5%"
+        );
+        assert_eq!(round_trip(&written), written);
+    }
+
+    #[test]
+    fn serializes_very_deep_documents_without_recursion() {
+        let depth = 10_000;
+        let mut value = BellandeValue::Integer(1);
+        for _ in 0..depth {
+            value = BellandeValue::List(vec![value]);
+        }
+
+        let written = BellandeFormat.to_bellande_string(&value, 0, &WriteOptions::default());
+        assert_eq!(written, format!("{}1", "- ".repeat(depth)));
+
+        // Dismantle iteratively; the default drop would recurse once per level.
+        while let BellandeValue::List(mut items) = value {
+            value = items.pop().unwrap_or(BellandeValue::Null);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn expands_environment_references() {
+        std::env::set_var("BELLANDE_TEST_PORT", "9090");
+        std::env::set_var("BELLANDE_TEST_HOST", "example.com");
+        std::env::remove_var("BELLANDE_TEST_UNSET");
+        let value = parse(
+            "port: ${BELLANDE_TEST_PORT}\nurl: http://${BELLANDE_TEST_HOST}:${BELLANDE_TEST_PORT}/\nmode: ${BELLANDE_TEST_UNSET:-dev}\nempty: x${BELLANDE_TEST_UNSET}y\n",
+        );
+        assert_eq!(
+            value.expand_env_vars(),
+            map(vec![
+                ("port", BellandeValue::Integer(9090)),
+                ("url", string("http://example.com:9090/")),
+                ("mode", string("dev")),
+                ("empty", string("xy")),
+            ])
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn environment_overrides_single_settings() {
+        std::env::set_var("BELLANDE_TEST_OVERRIDE_PORT", "9090");
+        std::env::remove_var("BELLANDE_TEST_OVERRIDE_UNSET");
+        let value = parse("server:\n  port: 8080\n  host: localhost\n");
+        assert_eq!(
+            value.get_with_env_override("server.port", "BELLANDE_TEST_OVERRIDE_PORT"),
+            Some(BellandeValue::Integer(9090))
+        );
+        assert_eq!(
+            value.get_with_env_override("server.host", "BELLANDE_TEST_OVERRIDE_UNSET"),
+            Some(string("localhost"))
+        );
+        assert_eq!(
+            value.get_with_env_override("server.tls", "BELLANDE_TEST_OVERRIDE_UNSET"),
+            None
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn load_config_prefers_file_over_defaults() {
+        std::env::set_var("BELLANDE_TEST_LOG", "debug");
+        let path = temp_path("load_config.bellande");
+        fs::write(&path, "server:\n  port: 8080\n").unwrap();
+        let defaults = parse("server:\n  port: 80\n  host: localhost\nlog: ${BELLANDE_TEST_LOG}\n");
+
+        assert_eq!(
+            BellandeFormat.load_config(&path, &defaults).unwrap(),
+            parse("server:\n  port: 8080\n  host: localhost\nlog: debug\n")
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn parses_items_whose_content_starts_on_the_next_line() {
+        let parsed = parse(
+            "servers:\n  -\n    host: a\n    port: 1\n  -\n    host: b\n  -\n  - last\nmatrix:\n  -\n    - 1\n    - 2\n  - - 3\n    - 4\n",
+        );
+        assert_eq!(
+            parsed,
+            map(vec![
+                (
+                    "servers",
+                    BellandeValue::List(vec![
+                        map(vec![
+                            ("host", string("a")),
+                            ("port", BellandeValue::Integer(1))
+                        ]),
+                        map(vec![("host", string("b"))]),
+                        BellandeValue::Null,
+                        string("last"),
+                    ])
+                ),
+                (
+                    "matrix",
+                    BellandeValue::List(vec![
+                        BellandeValue::List(vec![
+                            BellandeValue::Integer(1),
+                            BellandeValue::Integer(2)
+                        ]),
+                        BellandeValue::List(vec![
+                            BellandeValue::Integer(3),
+                            BellandeValue::Integer(4)
+                        ]),
+                    ])
+                ),
+            ])
+        );
+        assert_eq!(round_trip(&parsed), parsed);
+    }
+
+    #[test]
+    fn verifies_that_documents_survive_a_round_trip() {
+        assert!(BellandeFormat::verify_roundtrip(
+            "name: \"a b\"\nitems:\n  - {k: v}\n  - \"007\"\nm: {\"\": null}\n"
+        )
+        .is_ok());
+        assert!(matches!(
+            BellandeFormat::verify_roundtrip("a: [1\n"),
+            Err(BellandeError::Parse { .. })
+        ));
+
+        // A key starting with `- ` is written unquoted and read back as a
+        // list item.
+        let err = BellandeFormat::verify_roundtrip("m: {- x: 1}\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`m.- x`: `1` was read back as nothing (and 1 more change)"
+        );
+        let err = BellandeFormat::verify_roundtrip("a.b: {- x: 1}\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`a.b.- x`: `1` was read back as nothing (and 1 more change)"
+        );
+    }
+
+    #[test]
+    fn sniffs_bellande_documents() {
+        assert!(BellandeFormat::looks_like_bellande(
+            "# config\nname: demo\nskills:\n  - a\n"
+        ));
+        assert!(!BellandeFormat::looks_like_bellande("{\"name\": \"demo\"}"));
+        assert!(!BellandeFormat::looks_like_bellande(
+            "just some prose here\n"
+        ));
+        assert!(!BellandeFormat::looks_like_bellande(""));
+    }
+
+    #[test]
+    fn detects_indent_width() {
+        assert_eq!(
+            BellandeFormat::detect_indent_width("a:\n    b: 1\n    c:\n        - x\nd: 2\n"),
+            Some(4)
+        );
+        assert_eq!(
+            BellandeFormat::detect_indent_width("a:\n  b:\n    c: 1\n"),
+            Some(2)
+        );
+        assert_eq!(BellandeFormat::detect_indent_width("a: 1\n"), None);
+    }
+
+    #[test]
+    fn decodes_escapes_in_quoted_strings() {
+        let parsed = parse(
+            "emoji: \"\\u{1F600}\"\nbmp: \"caf\\u00E9\"\npair: \"\\uD83D\\uDE00\"\nmixed: \"a\\tb\\n\\\"c\\\"\"\nliteral: \"C:\\dir\"\nunquoted: \\u00E9\n",
+        );
+        assert_eq!(
+            parsed,
+            map(vec![
+                ("emoji", string("\u{1F600}")),
+                ("bmp", string("café")),
+                ("pair", string("\u{1F600}")),
+                ("mixed", string("a\tb\n\"c\"")),
+                ("literal", string("C:\\dir")),
+                ("unquoted", string("\\u00E9")),
+            ])
+        );
+    }
+
+    #[test]
+    fn round_trips_unicode_and_control_characters() {
+        let value = map(vec![
+            ("emoji", string("smile \u{1F600}")),
+            ("combining", string("e\u{0301}")),
+            ("control", string("bell\u{7}\u{85}")),
+            ("quotes", string("\"quoted\" \\ path")),
+            ("multiline", string("one\ntwo")),
+        ]);
+        assert_eq!(round_trip(&value), value);
+
+        let options = WriteOptions {
+            escape_non_ascii: true,
+            ..WriteOptions::default()
+        };
+        let written = BellandeFormat.to_bellande_string(
+            &map(vec![("emoji", string("\u{1F600}"))]),
+            0,
+            &options,
+        );
+        assert_eq!(written, "emoji: \"\\u{1F600}\"");
+        assert_eq!(parse(&written), map(vec![("emoji", string("\u{1F600}"))]));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn applies_patches_and_writes_the_result() {
+        let mut value = parse("server:\n  port: 80\nprojects:\n  - name: a\nname: x\nempty:\n");
+        value
+            .apply_patches(&[
+                ("server.port".to_string(), BellandeValue::Integer(8080)),
+                (
+                    "server.tls.enabled".to_string(),
+                    BellandeValue::Boolean(true),
+                ),
+                ("projects.0.name".to_string(), string("b")),
+                ("projects.1".to_string(), string("c")),
+                ("version".to_string(), BellandeValue::Integer(2)),
+                ("empty.key".to_string(), BellandeValue::Null),
+            ])
+            .unwrap();
+
+        let path = temp_path("patched.bellande");
+        BellandeFormat.write_bellande(&value, &path).unwrap();
+        assert_eq!(
+            BellandeFormat.parse_bellande(&path).unwrap(),
+            map(vec![
+                (
+                    "server",
+                    map(vec![
+                        ("port", BellandeValue::Integer(8080)),
+                        ("tls", map(vec![("enabled", BellandeValue::Boolean(true))])),
+                    ])
+                ),
+                (
+                    "projects",
+                    BellandeValue::List(vec![map(vec![("name", string("b"))]), string("c")])
+                ),
+                ("name", string("x")),
+                ("version", BellandeValue::Integer(2)),
+                ("empty", map(vec![("key", BellandeValue::Null)])),
+            ])
+        );
+        fs::remove_file(path).unwrap();
+
+        let err = value
+            .apply_patches(&[
+                ("name.first".to_string(), string("y")),
+                ("version".to_string(), BellandeValue::Integer(3)),
+            ])
+            .unwrap_err();
+        assert!(matches!(err, BellandeError::Validation { ref path, .. } if path == "name.first"));
+        assert_eq!(value.get_path("version"), Some(&BellandeValue::Integer(2)));
+    }
+
+    #[test]
+    fn resolves_profiled_keys() {
+        let content = "port@prod: 443\nport: 80\nhost@dev: localhost\nhost: example.com\n\
+                       db:\n  name@prod: main\n  name: test\n";
+        let options = |profile: Option<&str>| ParseOptions {
+            profile: profile.map(str::to_string),
+            ..ParseOptions::default()
+        };
+
+        let prod = BellandeFormat
+            .parse_bellande_str_with_options(content, &options(Some("prod")))
+            .unwrap();
+        assert_eq!(
+            prod,
+            map(vec![
+                ("port", BellandeValue::Integer(443)),
+                ("host", string("example.com")),
+                ("db", map(vec![("name", string("main"))])),
+            ])
+        );
+
+        let dev = BellandeFormat
+            .parse_bellande_str_with_options(content, &options(Some("dev")))
+            .unwrap();
+        assert_eq!(dev.get_path("port"), Some(&BellandeValue::Integer(80)));
+        assert_eq!(dev.get_path("host"), Some(&string("localhost")));
+
+        let verbatim = BellandeFormat
+            .parse_bellande_str_with_options(content, &options(None))
+            .unwrap();
+        assert_eq!(
+            verbatim.get_path("port@prod"),
+            Some(&BellandeValue::Integer(443))
+        );
+    }
+
+    #[test]
+    fn lists_every_leaf_path() {
+        let value =
+            parse("server:\n  port: 80\n  tags: []\nprojects:\n  - name: a\n  - b\nname: x\n");
+        assert_eq!(
+            value.paths(),
+            vec![
+                "name",
+                "projects.0.name",
+                "projects.1",
+                "server.port",
+                "server.tags"
+            ]
+        );
+        for path in value.paths() {
+            assert!(value.get_path(&path).is_some());
+        }
+        assert_eq!(string("x").paths(), vec![String::new()]);
+
+        let long = BellandeValue::List((0..11).map(BellandeValue::Integer).collect());
+        let paths = long.paths();
+        assert_eq!((paths[2].as_str(), paths[10].as_str()), ("2", "10"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn writes_and_skips_a_document_start_marker() {
+        let value = map(vec![("name", string("x"))]);
+        let path = temp_path("document_start.bellande");
+        let options = WriteOptions {
+            document_start: true,
+            ..WriteOptions::default()
+        };
+        BellandeFormat
+            .write_bellande_with_options(&value, &path, &options)
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "---\nname: x");
+        assert_eq!(BellandeFormat.parse_bellande(&path).unwrap(), value);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(parse("# header\n---\nname: x\n"), value);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn skips_and_keeps_a_shebang_line() {
+        let value = map(vec![("name", string("x"))]);
+        let options = ParseOptions {
+            comment_prefixes: vec![";".to_string()],
+            ..ParseOptions::default()
+        };
+        let source = "#!/usr/bin/env bellande-run\n---\nname: x\n";
+        assert_eq!(
+            BellandeFormat
+                .parse_bellande_str_with_options(source, &options)
+                .unwrap(),
+            value
+        );
+
+        let document = BellandeFormat.parse_document(source);
+        assert_eq!(document.to_source(), source);
+        assert_eq!(document.format(), source);
+
+        let path = temp_path("shebang.bellande");
+        let options = WriteOptions {
+            document_start: true,
+            shebang: Some("#!/usr/bin/env bellande-run".to_string()),
+            ..WriteOptions::default()
+        };
+        BellandeFormat
+            .write_bellande_with_options(&value, &path, &options)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "#!/usr/bin/env bellande-run\n---\nname: x"
+        );
+        assert_eq!(BellandeFormat.parse_bellande(&path).unwrap(), value);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn keeps_a_header_comment_on_write() {
+        let source = "#!/usr/bin/env run\n# Copyright (C) 2024 Example\n# SPDX-License-Identifier: GPL-3.0\n\n# Not part of the header\nname: x\n";
+        let header = BellandeFormat::header_comment(source).unwrap();
+        assert_eq!(
+            header,
+            "# Copyright (C) 2024 Example\n# SPDX-License-Identifier: GPL-3.0"
+        );
+        assert_eq!(BellandeFormat::header_comment("name: x\n# late\n"), None);
+
+        let path = temp_path("header.bellande");
+        let options = WriteOptions {
+            header: Some(header.to_string()),
+            document_start: true,
+            ..WriteOptions::default()
+        };
+        let value = map(vec![("name", string("y"))]);
+        BellandeFormat
+            .write_bellande_with_options(&value, &path, &options)
+            .unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            written,
+            "# Copyright (C) 2024 Example\n# SPDX-License-Identifier: GPL-3.0\n---\nname: y"
+        );
+        assert_eq!(BellandeFormat::header_comment(&written), Some(header));
+        assert_eq!(BellandeFormat.parse_bellande(&path).unwrap(), value);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn accepts_a_single_value_or_a_list() {
+        let value = parse("one: a\nmany: [a, b]\n");
+        assert_eq!(
+            value.get_path("one").unwrap().as_list_or_single(),
+            vec![&string("a")]
+        );
+        assert_eq!(
+            value.get_path("many").unwrap().as_list_or_single(),
+            vec![&string("a"), &string("b")]
+        );
+    }
+
+    #[test]
+    fn keeps_keys_that_start_with_a_dash() {
+        let content = "-x: 1\nopts:\n  --verbose: true\n  list:\n    - a\n    -b\n";
+        let reformatted = BellandeFormat::reformat(content, &WriteOptions::default()).unwrap();
+        assert_eq!(
+            reformatted,
+            "-x: 1\nopts:\n  --verbose: true\n  list:\n    - a\n    - b\n"
+        );
+        assert_eq!(parse(&reformatted), parse(content));
+        let normalized = BellandeFormat::normalize_indentation(content, 4).unwrap();
+        assert_eq!(parse(&normalized), parse(content));
+        assert_eq!(
+            parse(content).get_path("-x"),
+            Some(&BellandeValue::Integer(1))
+        );
+    }
+
+    #[test]
+    fn reformats_while_keeping_comments_and_values() {
+        let content = "---\n# settings\nname:   \"John\"   # who\nzip: 007\nport: \"80\"\n\n\n\
+                       server:\n      host: \"caf\\u00E9\"\n      tags: [\"a\", b]\nitems:\n    - \"x\"\n    - key: \"v\"\n";
+        let reformatted = BellandeFormat::reformat(content, &WriteOptions::default()).unwrap();
+        assert_eq!(
+            reformatted,
+            "---\n# settings\nname: \"John\" # who\nzip: 007\nport: \"80\"\n\nserver:\n  host: \"café\"\n  \
+             tags: [\"a\", b]\nitems:\n  - \"x\"\n  - key: \"v\"\n"
+        );
+        assert_eq!(parse(&reformatted), parse(content));
+
+        let escaped = WriteOptions {
+            escape_non_ascii: true,
+            document_start: true,
+            ..WriteOptions::default()
+        };
+        assert_eq!(
+            BellandeFormat::reformat("host: café\n", &escaped).unwrap(),
+            "---\nhost: \"caf\\u{E9}\"\n"
+        );
+        assert!(BellandeFormat::reformat("key: [1, 2\n", &escaped).is_err());
+    }
+
+    #[test]
+    fn normalizes_mixed_indentation() {
+        let content = "server:\n   host: x\n   # tls\n   tls:\n       on: true\nlist:\n - a\n \
+                       - name: b\n   port: 1\n";
+        let normalized = BellandeFormat::normalize_indentation(content, 4).unwrap();
+        assert_eq!(
+            normalized,
+            "server:\n    host: x\n    # tls\n    tls:\n        on: true\nlist:\n    - a\n    \
+             - name: b\n      port: 1\n"
+        );
+        assert_eq!(parse(&normalized), parse(content));
+
+        let ambiguous = "a:\n    b: 1\n  c: 2\n";
+        let (_, warnings) = BellandeFormat
+            .parse_bellande_str_with_warnings(ambiguous, &ParseOptions::default())
+            .unwrap();
+        assert_eq!(warnings[0].kind, WarningKind::IndentationMismatch);
+        let err = BellandeFormat::normalize_indentation(ambiguous, 2).unwrap_err();
+        assert!(
+            matches!(err, BellandeError::Parse { line: 3, .. }),
+            "{}",
+            err
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn reports_progress_while_parsing() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ParseOptions>();
+
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let record = Arc::clone(&seen);
+        let options = ParseOptions {
+            progress: Some(ProgressHook::new(move |line| {
+                record.lock().unwrap().push(line)
+            })),
+            ..ParseOptions::default()
+        };
+        let content: String = (0..10_000).map(|i| format!("k{}: {}\n", i, i)).collect();
+        let value = BellandeFormat
+            .parse_bellande_str_with_options(&content, &options)
+            .unwrap();
+        assert_eq!(value.as_map().unwrap().len(), 10_000);
+        assert_eq!(*seen.lock().unwrap(), vec![4096, 8192, 10_000]);
+    }
+
+    #[test]
+    fn measures_tab_indentation_with_tab_stops() {
+        let tabbed =
+            "server:\n\thost: a\n\ttls:\n\t\ton: true\nlist:\n\t- a\n\t-\tname: b\n\t\tport: 1\n";
+        assert_eq!(
+            parse(tabbed),
+            parse("server:\n  host: a\n  tls:\n    on: true\nlist:\n  - a\n  - name: b\n    port: 1\n")
+        );
+
+        let (value, warnings) = BellandeFormat
+            .parse_bellande_str_with_warnings(
+                "a:\n        b: 1\n  \tc: 2\n",
+                &ParseOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(value, parse("a:\n  b: 1\n  c: 2\n"));
+        let warnings: Vec<_> = warnings
+            .iter()
+            .map(|w| (w.line, w.message.as_str()))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![(
+                3,
+                "indentation mixes tabs and spaces; read as 8 columns with tab stops every 8"
+            )]
+        );
+
+        let narrow = ParseOptions {
+            tab_width: 4,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            BellandeFormat
+                .parse_bellande_str_with_options("a:\n    b: 1\n\tc: 2\n", &narrow)
+                .unwrap(),
+            parse("a:\n  b: 1\n  c: 2\n")
+        );
+    }
+
+    #[test]
+    fn consults_a_custom_scalar_resolver_first() {
+        let options = ParseOptions {
+            scalar_resolver: Some(ScalarResolver::new(|value| {
+                let octets: Vec<&str> = value.split('.').collect();
+                (octets.len() == 4 && octets.iter().all(|o| o.parse::<u8>().is_ok())).then(|| {
+                    BellandeValue::List(
+                        octets
+                            .iter()
+                            .map(|o| BellandeValue::Integer(o.parse().unwrap()))
+                            .collect(),
+                    )
+                })
+            })),
+            ..ParseOptions::default()
+        };
+        let value = BellandeFormat
+            .parse_bellande_str_with_options(
+                "ip: 10.0.0.1\nquoted: \"10.0.0.2\"\nport: 80\nhosts: [127.0.0.1]\n",
+                &options,
+            )
+            .unwrap();
+        let ip = |octets: [i64; 4]| {
+            BellandeValue::List(octets.into_iter().map(BellandeValue::Integer).collect())
+        };
+        assert_eq!(
+            value,
+            map(vec![
+                ("ip", ip([10, 0, 0, 1])),
+                ("quoted", string("10.0.0.2")),
+                ("port", BellandeValue::Integer(80)),
+                ("hosts", BellandeValue::List(vec![ip([127, 0, 0, 1])])),
+            ])
+        );
+    }
+
+    #[test]
+    fn reads_untagged_values_as_strings_without_type_inference() {
+        let options = ParseOptions {
+            infer_types: false,
+            ..ParseOptions::default()
+        };
+        let parse_strict =
+            |content: &str| BellandeFormat.parse_bellande_str_with_options(content, &options);
+        assert_eq!(
+            parse_strict(
+                "id: 007\nversion: 1.10\nenabled: true\nempty: null\nname: \"a\\tb\"\n\
+                 port: !int 8080\nratio: !float 2\ndebug: !bool FALSE\nlist: [1, !int 2]\n"
+            )
+            .unwrap(),
+            map(vec![
+                ("id", string("007")),
+                ("version", string("1.10")),
+                ("enabled", string("true")),
+                ("empty", string("null")),
+                ("name", string("a\tb")),
+                ("port", BellandeValue::Integer(8080)),
+                ("ratio", BellandeValue::Float(2.0)),
+                ("debug", BellandeValue::Boolean(false)),
+                (
+                    "list",
+                    BellandeValue::List(vec![string("1"), BellandeValue::Integer(2)])
+                ),
+            ])
+        );
+        assert_eq!(
+            parse("port: !int 80\n"),
+            map(vec![("port", BellandeValue::Integer(80))])
+        );
+
+        let err = parse_strict("name: x\nport: !int eighty\n").unwrap_err();
+        assert!(matches!(
+            err,
+            BellandeError::Parse { line: 2, ref message, .. }
+                if message == "`eighty` is not a valid !int"
+        ));
+        assert!(parse_strict("flags: [!bool yes]\n").is_err());
+    }
+
+    #[test]
+    fn parses_numbers_with_a_fixed_grammar() {
+        let value = parse(
+            "int: -42\nplus: +7\nfloat: 1.5\ntrailing_dot: 1.\nleading_dot: .5\nexp: 2e3\n\
+             neg_exp: -1.5E-2\nbig: 99999999999999999999\nhuge: 1e400\ninf: inf\nnan: NaN\n\
+             comma: 1,5\ndots: 1.2.3\nbare_exp: 1e\nsign: -\nhex: 0x10\nunderscore: 1_000\n",
+        );
+        let expected = [
+            ("int", BellandeValue::Integer(-42)),
+            ("plus", BellandeValue::Integer(7)),
+            ("float", BellandeValue::Float(1.5)),
+            ("trailing_dot", BellandeValue::Float(1.0)),
+            ("leading_dot", BellandeValue::Float(0.5)),
+            ("exp", BellandeValue::Float(2000.0)),
+            ("neg_exp", BellandeValue::Float(-0.015)),
+            ("big", string("99999999999999999999")),
+            ("huge", string("1e400")),
+            ("inf", string("inf")),
+            ("nan", string("NaN")),
+            ("comma", string("1,5")),
+            ("dots", string("1.2.3")),
+            ("bare_exp", string("1e")),
+            ("sign", string("-")),
+            ("hex", string("0x10")),
+            ("underscore", string("1_000")),
+        ];
+        for (key, expected) in expected {
+            assert_eq!(value.get_path(key), Some(&expected), "{}", key);
+        }
+        assert_eq!(round_trip(&value), value);
+    }
+
+    #[test]
+    fn inserts_entries_with_comments() {
+        let mut document = BellandeFormat.parse_document("# app\nname: x\n\n# old\nport: 80\n");
+        document.insert_with_comment("port", &BellandeValue::Integer(8080), "listen port");
+        document.insert_with_comment(
+            "server",
+            &map(vec![("host", string("localhost"))]),
+            "where to connect\nchanged by the installer",
+        );
+        assert_eq!(
+            document.to_source(),
+            "# app\nname: x\n\n# listen port\nport: 8080\n# where to connect\n\
+             # changed by the installer\nserver:\n  host: localhost\n"
+        );
+        assert_eq!(
+            parse(&document.to_source()),
+            map(vec![
+                ("name", string("x")),
+                ("port", BellandeValue::Integer(8080)),
+                ("server", map(vec![("host", string("localhost"))])),
+            ])
+        );
+    }
+
+    #[test]
+    fn builds_patches_that_merge_back() {
+        let base = parse("server:\n  host: a\n  port: 80\ntags: [x]\nname: app\nold: 1\n");
+        let current =
+            parse("server:\n  host: a\n  port: 8080\ntags: [x, y]\nname: app\nnew: true\n");
+        let patch = current.to_patch(&base);
+        assert_eq!(
+            patch,
+            map(vec![
+                ("server", map(vec![("port", BellandeValue::Integer(8080))])),
+                ("tags", BellandeValue::List(vec![string("x"), string("y")])),
+                ("new", BellandeValue::Boolean(true)),
+                ("old", BellandeValue::Null),
+            ])
+        );
+
+        let mut synced = base.clone();
+        synced.merge(patch);
+        assert_eq!(synced.without_keys(&["old"]), current);
+        assert_eq!(current.to_patch(&current), map(vec![]));
+    }
+
+    #[test]
+    fn rejects_documents_over_max_nodes() {
+        let options = |max_nodes| ParseOptions {
+            max_nodes: Some(max_nodes),
+            ..ParseOptions::default()
+        };
+        let source = "server:\n  host: a\n  port: 80\nhosts:\n  - a\n  - {b: 1}\n";
+        assert!(BellandeFormat
+            .parse_bellande_str_with_options(source, &options(7))
+            .is_ok());
+        let err = BellandeFormat
+            .parse_bellande_str_with_options(source, &options(6))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "limit exceeded: more than 6 nodes by line 6"
+        );
+
+        let wide = "list: [".to_string() + &"1, ".repeat(1000) + "1]\n";
+        assert!(matches!(
+            BellandeFormat.parse_bellande_str_with_options(&wide, &options(100)),
+            Err(BellandeError::LimitExceeded(_))
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn rejects_lines_over_the_limit() {
+        let options = ParseOptions {
+            max_line_bytes: Some(16),
+            ..ParseOptions::default()
+        };
+        let ok = "name: short\r\nport: 8080\n";
+        let long = format!("name: short\nvalue: {}\n", "x".repeat(64));
+
+        assert!(BellandeFormat
+            .parse_bellande_str_with_options(ok, &options)
+            .is_ok());
+        let err = BellandeFormat
+            .parse_bellande_str_with_options(&long, &options)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "limit exceeded: line 2 is longer than 16 bytes"
+        );
+
+        let path = temp_path("long_line.bellande");
+        fs::write(&path, ok).unwrap();
+        assert_eq!(
+            BellandeFormat
+                .parse_bellande_with_options(&path, &options)
+                .unwrap(),
+            map(vec![
+                ("name", string("short")),
+                ("port", BellandeValue::Integer(8080))
+            ])
+        );
+        fs::write(&path, format!("{}{}", long, "y".repeat(1 << 20))).unwrap();
+        let err = BellandeFormat
+            .parse_bellande_with_options(&path, &options)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "limit exceeded: line 2 is longer than 16 bytes"
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn exports_env_style_pairs() {
+        let value = parse(
+            "server:\n  port: 8080\n  host-name: db\nratio: 1.0\ndebug: true\nhosts:\n  - a\n  - b\nunset: null\nempty: []\n",
+        );
+        assert_eq!(
+            value.to_env("app"),
+            vec![
+                ("APP_DEBUG".to_string(), "true".to_string()),
+                ("APP_HOSTS_0".to_string(), "a".to_string()),
+                ("APP_HOSTS_1".to_string(), "b".to_string()),
+                ("APP_RATIO".to_string(), "1.0".to_string()),
+                ("APP_SERVER_HOST_NAME".to_string(), "db".to_string()),
+                ("APP_SERVER_PORT".to_string(), "8080".to_string()),
+                ("APP_UNSET".to_string(), String::new()),
+            ]
+        );
+        assert_eq!(value.to_env("")[0].0, "DEBUG");
+        assert_eq!(
+            parse("a.b: 1\nc: 2\n").to_env("app"),
+            vec![
+                ("APP_A_B".to_string(), "1".to_string()),
+                ("APP_C".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_blocks_mixing_list_items_and_map_entries() {
+        let err = BellandeFormat
+            .parse_bellande_str("key:\n  - a\n  sub: b\n")
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            BellandeError::Parse {
+                line: 3,
+                column: 3,
+                ..
+            }
+        ));
+        let err = BellandeFormat
+            .parse_bellande_str("key:\n  sub: b\n  - a\n")
+            .unwrap_err();
+        assert!(matches!(err, BellandeError::Parse { line: 3, .. }));
+        assert_eq!(
+            parse("key:\n  - name: a\n    sub: b\n"),
+            map(vec![(
+                "key",
+                BellandeValue::List(vec![map(vec![("name", string("a")), ("sub", string("b"))])])
+            )])
+        );
+    }
+
+    #[test]
+    fn builds_nested_documents() {
+        let built = BellandeBuilder::new()
+            .map()
+            .key("server")
+            .map()
+            .key("host")
+            .string("localhost")
+            .key("port")
+            .int(8080)
+            .end()
+            .key("hosts")
+            .list()
+            .string("a")
+            .map()
+            .key("weight")
+            .float(0.5)
+            .end()
+            .end()
+            .key("debug")
+            .bool(true)
+            .build()
+            .unwrap();
+        let expected = parse(
+            "server:\n  host: localhost\n  port: 8080\nhosts:\n  - a\n  - weight: 0.5\ndebug: true\n",
+        );
+        assert_eq!(built, expected);
+        assert_eq!(round_trip(&built), expected);
+
+        let err = BellandeBuilder::new()
+            .map()
+            .key("server")
+            .list()
+            .key("port")
+            .int(1)
+            .build()
+            .unwrap_err();
+        assert!(
+            matches!(err, BellandeError::Validation { ref path, .. } if path == "server.0.port")
+        );
+        assert!(BellandeBuilder::new().int(1).end().build().is_err());
+        assert!(BellandeBuilder::new().map().key("a").build().is_err());
+    }
+
+    #[test]
+    fn keeps_comments_on_and_after_list_items() {
+        let source = "servers:\n    - name: a  # primary\n      port: 1\n      # handles writes\n    - # spare\n      name: b\n    - c\n      # last one\nother: 1\n";
+        let document = BellandeFormat.parse_document(source);
+        assert_eq!(document.to_source(), source);
+        assert_eq!(
+            document.format(),
+            "servers:\n  - name: a  # primary\n    port: 1\n    # handles writes\n  - # spare\n    name: b\n  - c\n    # last one\nother: 1\n"
+        );
+        assert_eq!(
+            document.format_with_options(&WriteOptions::default()),
+            document.format()
+        );
+        assert_eq!(
+            parse(source),
+            parse("servers:\n  - name: a\n    port: 1\n  - name: b\n  - c\nother: 1\n")
+        );
+    }
+
+    #[test]
+    fn merges_documents_keeping_comments() {
+        let base = "# Service name\nname: api\n\n# Server settings\nserver:\n  # Listen port\n  port: 80\n  host: a\n";
+        let overlay = "name: web\nserver:\n    # Overridden for prod\n    port: 8080\n    # TLS on in prod\n    tls: true\n# Replica count\nreplicas: 3\n";
+        let mut document = BellandeFormat.parse_document(base);
+        document.merge(BellandeFormat.parse_document(overlay));
+        assert_eq!(
+            document.to_source(),
+            "# Service name\nname: web\n\n# Server settings\nserver:\n  # Overridden for prod\n  port: 8080\n  host: a\n  # TLS on in prod\n  tls: true\n# Replica count\nreplicas: 3\n"
+        );
+
+        let mut merged = parse(base);
+        merged.merge(parse(overlay));
+        assert_eq!(parse(&document.to_source()), merged);
+    }
+
+    #[test]
+    fn reorders_document_keys() {
+        let mut document = BellandeFormat.parse_document(
+            "# service\nname: api\n# where it listens\nserver:\n  port: 80\n  host: a\n  tls: true\nworkers:\n  - id: 1\n    zone: x\n    cpu: 2\n",
+        );
+        document.reorder_keys("", &["server", "workers"]).unwrap();
+        document.reorder_keys("server", &["host"]).unwrap();
+        document.reorder_keys("workers.0", &["cpu"]).unwrap();
+        assert_eq!(
+            document.to_source(),
+            "# where it listens\nserver:\n  host: a\n  port: 80\n  tls: true\nworkers:\n  - id: 1\n    cpu: 2\n    zone: x\n# service\nname: api\n"
+        );
+        assert!(matches!(
+            document.reorder_keys("server.missing", &[]),
+            Err(BellandeError::MissingPath(_))
+        ));
+    }
+
+    #[test]
+    fn keeps_each_string_in_its_quote_style() {
+        let document = BellandeFormat
+            .parse_document("plain: abc\ndouble: \"abc\" # note\nsingle: 'a b'\nforced: a\tb\n");
+        let styles: Vec<QuoteStyle> = document
+            .nodes
+            .iter()
+            .filter_map(|node| match &node.kind {
+                CstNodeKind::Entry { value, .. } => value.as_ref().map(|value| value.quote),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            styles,
+            [
+                QuoteStyle::Plain,
+                QuoteStyle::Double,
+                QuoteStyle::Single,
+                QuoteStyle::Plain
+            ]
+        );
+        assert_eq!(
+            document.format_with_options(&WriteOptions::default()),
+            "plain: abc\ndouble: \"abc\" # note\nsingle: 'a b'\nforced: \"a\\tb\"\n"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn reports_the_offset_of_invalid_utf8() {
+        let err = BellandeFormat
+            .parse_bellande_bytes(b"name: caf\xe9\nport: 80\n")
+            .unwrap_err();
+        assert!(matches!(err, BellandeError::InvalidUtf8 { offset: 9, .. }));
+        assert_eq!(
+            err.to_string(),
+            "invalid UTF-8 at byte 9: 61 6d 65 3a 20 63 61 66 [e9] 0a 70 6f 72 74 3a 20 38"
+        );
+        assert!(matches!(
+            BellandeFormat.parse_bellande_bytes(b"truncated: \xe2\x82"),
+            Err(BellandeError::InvalidUtf8 { offset: 11, ref context }) if context.ends_with("[e2 82]")
+        ));
+
+        let path = temp_path("latin1.bellande");
+        fs::write(&path, b"a: 1\nb: \xff\n").unwrap();
+        assert!(matches!(
+            BellandeFormat.parse_bellande(&path),
+            Err(BellandeError::InvalidUtf8 { offset: 8, .. })
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn required_getters_name_the_failing_path() {
+        let value = parse("server:\n  host: a\n  port: 80\n  ratio: 0.5\n  tls: true\n");
+        assert_eq!(value.get_required("server.host").unwrap(), &string("a"));
+        assert_eq!(value.get_str("server.host").unwrap(), "a");
+        assert_eq!(value.get_i64("server.port").unwrap(), 80);
+        assert_eq!(value.get_f64("server.port").unwrap(), 80.0);
+        assert!(value.get_bool("server.tls").unwrap());
+
+        let missing = value.get_i64("server.timeout").unwrap_err();
+        assert!(
+            matches!(missing, BellandeError::MissingPath(ref path) if path == "server.timeout")
+        );
+        assert_eq!(
+            value.get_i64("server.ratio").unwrap_err().to_string(),
+            "`server.ratio`: expected integer, found float"
+        );
+    }
+
+    #[test]
+    fn flattens_values_into_log_fields() {
+        let value = parse(
+            "server:\n  host: my host\n  port: 8080\n  tags: []\nreplicas:\n  - 1.5\n  - null\n",
+        );
+        assert_eq!(
+            value.to_log_fields(),
+            [
+                ("replicas.0".to_string(), "1.5".to_string()),
+                ("replicas.1".to_string(), "null".to_string()),
+                ("server.host".to_string(), "my host".to_string()),
+                ("server.port".to_string(), "8080".to_string()),
+                ("server.tags".to_string(), "[]".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse("a.b: 1\nc: 2\n").to_log_fields(),
+            [
+                ("a.b".to_string(), "1".to_string()),
+                ("c".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_canonical_json() {
+        let value = map(vec![
+            (
+                "b",
+                BellandeValue::List(vec![BellandeValue::Integer(1), BellandeValue::Null]),
+            ),
+            ("a", string("tab\there \"q\" \u{1} é")),
+            ("\u{20ac}", BellandeValue::Boolean(true)),
+            ("\u{1f600}", BellandeValue::Boolean(false)),
+            ("\u{fb33}", BellandeValue::Null),
+            (
+                "nested",
+                map(vec![("z", map(vec![])), ("y", BellandeValue::List(vec![]))]),
+            ),
+        ]);
+        assert_eq!(
+            value.canonical_json(),
+            "{\"a\":\"tab\\there \\\"q\\\" \\u0001 é\",\"b\":[1,null],\
+             \"nested\":{\"y\":[],\"z\":{}},\"\u{20ac}\":true,\"\u{1f600}\":false,\"\u{fb33}\":null}"
+        );
+
+        let numbers = [
+            (0.0, "0"),
+            (-0.0, "0"),
+            (2.0, "2"),
+            (-1.5, "-1.5"),
+            (1e21, "1e+21"),
+            (1e20, "100000000000000000000"),
+            (123456789.125, "123456789.125"),
+            (0.000001, "0.000001"),
+            (1.5e-7, "1.5e-7"),
+            (4.5e-300, "4.5e-300"),
+            (f64::NAN, "null"),
+        ];
+        for (number, expected) in numbers {
+            assert_eq!(BellandeValue::Float(number).canonical_json(), expected);
+        }
+        assert_eq!(
+            BellandeValue::Integer(i64::MAX).canonical_json(),
+            "9223372036854775807"
+        );
+    }
+
+    #[test]
+    fn hashes_the_semantic_content() {
+        let value = parse("# app\nname: demo\nserver:\n  port: 80\n  hosts: [a, b]\n");
+        let reordered =
+            parse("server:\n    hosts:\n      - a\n      - b\n    port: 80\nname: \"demo\"\n");
+        assert_eq!(value.content_hash(), reordered.content_hash());
+        assert_eq!(BellandeValue::Null.content_hash(), 0x5b9b_c4ba_5281_08e4);
+
+        let changed = parse("name: demo\nserver:\n  port: 81\n  hosts: [a, b]\n");
+        assert_ne!(value.content_hash(), changed.content_hash());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn digests_the_semantic_content_with_sha256() {
+        let digest = BellandeValue::Null.content_sha256();
+        assert_eq!(digest[..4], [0x74, 0x23, 0x4e, 0x98]);
+        assert_eq!(
+            parse("a: 1\nb: 2\n").content_sha256(),
+            parse("b: 2\na: 1\n").content_sha256()
+        );
+    }
+
+    #[test]
+    fn reports_every_unmet_type_requirement() {
+        let value = parse("name: api\nport: \"80\"\nhosts: [a]\n");
+        assert_eq!(
+            value
+                .expect_types(&[("name", "string"), ("hosts", "list")])
+                .unwrap(),
+            [&string("api"), &BellandeValue::List(vec![string("a")])]
+        );
+
+        let errors = value
+            .expect_types(&[("name", "string"), ("port", "integer"), ("tls", "boolean")])
+            .unwrap_err();
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "`port`: expected integer, found string",
+                "no value at path `tls`"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_a_document_against_an_example() {
+        let reference = parse(
+            "name: api\nratio: 0.5\nproxy: null\nserver:\n  port: 80\n  tls: true\n\
+             routes:\n  - path: /\n    methods: [GET]\n",
+        );
+        let good = parse(
+            "name: web\nratio: 1\nproxy: {host: p}\nserver:\n  port: 8080\n  tls: false\n\
+             routes:\n  - path: /a\n    methods: []\n  - path: /b\n    methods: [GET, PUT]\n",
+        );
+        assert!(good.matches_shape(&reference).is_ok());
+
+        let bad = parse(
+            "name: 7\nratio: 0.5\nproxy: null\nserver:\n  port: \"80\"\n  debug: true\n\
+             routes:\n  - path: /\n    methods: GET\n",
+        );
+        let errors = bad.matches_shape(&reference).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "`name`: expected string, found integer",
+                "`routes.0.methods`: expected list, found string",
+                "`server.debug`: key is not in the reference",
+                "`server.port`: expected integer, found string",
+                "no value at path `server.tls`",
+            ]
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn lowercases_keys_when_parsing_and_writing() {
+        let options = ParseOptions {
+            lowercase_keys: true,
+            ..ParseOptions::default()
+        };
+        let parsed = BellandeFormat
+            .parse_bellande_str_with_options(
+                "Content-Type: text\nRoutes:\n  - Path: /\n    X-Id: {Key: 1}\n",
+                &options,
+            )
+            .unwrap();
+        assert_eq!(
+            parsed,
+            map(vec![
+                ("content-type", string("text")),
+                (
+                    "routes",
+                    BellandeValue::List(vec![map(vec![
+                        ("path", string("/")),
+                        ("x-id", map(vec![("key", BellandeValue::Integer(1))])),
+                    ])])
+                ),
+            ])
+        );
+
+        let err = BellandeFormat
+            .parse_bellande_str_with_options("headers:\n  Host: a\n  host: b\n", &options)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`headers.host`: keys `Host` and `host` collide when lowercased"
+        );
+
+        let path = temp_path("lowercase.bellande");
+        let write_options = WriteOptions {
+            lowercase_keys: true,
+            ..WriteOptions::default()
+        };
+        BellandeFormat
+            .write_bellande_with_options(&map(vec![("Name", string("x"))]), &path, &write_options)
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "name: x");
+        let colliding = map(vec![("A", string("x")), ("a", string("y"))]);
+        assert!(BellandeFormat
+            .write_bellande_with_options(&colliding, &path, &write_options)
+            .is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn writes_only_values_that_differ_from_defaults() {
+        let defaults = parse(
+            "name: app\nserver:\n  host: localhost\n  port: 80\n  tls:\n    enabled: false\nretries: 3\ntags: [a]\n",
+        );
+        let current = parse(
+            "name: app\nserver:\n  host: localhost\n  port: 8080\n  tls:\n    enabled: false\nretries: null\ntags: [a, b]\nextra: 1\n",
+        );
+        let minimal = current.to_bellande_string_minimal(&defaults);
+        assert_eq!(
+            parse(&minimal),
+            map(vec![
+                ("server", map(vec![("port", BellandeValue::Integer(8080))])),
+                ("retries", BellandeValue::Null),
+                ("tags", BellandeValue::List(vec![string("a"), string("b")])),
+                ("extra", BellandeValue::Integer(1)),
+            ])
+        );
+        assert_eq!(parse(&minimal).with_defaults(&defaults), current);
+        assert_eq!(defaults.to_bellande_string_minimal(&defaults), "");
+    }
+
+    #[test]
+    fn writes_and_reads_each_null_style() {
+        let value = map(vec![
+            ("none", BellandeValue::Null),
+            (
+                "items",
+                BellandeValue::List(vec![BellandeValue::Null, string("~")]),
+            ),
+            ("nested", map(vec![("x", BellandeValue::Null)])),
+        ]);
+        for (style, spelling) in [
+            (NullStyle::Word, "none: null"),
+            (NullStyle::Tilde, "none: ~"),
+            (NullStyle::Empty, "none:"),
+        ] {
+            let write_options = WriteOptions {
+                null_style: style,
+                ..WriteOptions::default()
+            };
+            let parse_options = ParseOptions {
+                null_style: style,
+                ..ParseOptions::default()
+            };
+            let written = BellandeFormat.to_bellande_string(&value, 0, &write_options);
+            assert!(written.lines().any(|line| line == spelling), "{}", written);
+            assert_eq!(
+                BellandeFormat
+                    .parse_bellande_str_with_options(&written, &parse_options)
+                    .unwrap(),
+                value
+            );
+        }
+
+        assert_eq!(parse("a: ~\n"), map(vec![("a", string("~"))]));
+        assert_eq!(parse("a:\n"), map(vec![("a", BellandeValue::List(vec![]))]));
+    }
+
+    #[test]
+    fn drives_a_custom_writer() {
+        #[derive(Default)]
+        struct Xml(String, Vec<String>);
+        impl BellandeWriter for Xml {
+            fn scalar(&mut self, value: &BellandeValue) {
+                let text = BellandeFormat.format_value(value, &WriteOptions::default());
+                self.0.push_str(&text);
+                self.close_tag();
+            }
+            fn begin_map(&mut self, _keys: &[&str]) {
+                self.0.push_str("<map>");
+            }
+            fn key(&mut self, key: &str) {
+                self.0.push_str(&format!("<{}>", key));
+                self.1.push(key.to_string());
+            }
+            fn end_map(&mut self) {
+                self.0.push_str("</map>");
+                self.close_tag();
+            }
+            fn begin_list(&mut self, len: usize) {
+                self.0.push_str(&format!("<list len=\"{}\">", len));
+            }
+            fn item(&mut self, _index: usize) {
+                self.key("item");
+            }
+            fn end_list(&mut self) {
+                self.0.push_str("</list>");
+                self.close_tag();
+            }
+        }
+        impl Xml {
+            fn close_tag(&mut self) {
+                if let Some(tag) = self.1.pop() {
+                    self.0.push_str(&format!("</{}>", tag));
+                }
+            }
+        }
+
+        let value = parse("server:\n  hosts:\n    - a\n    - port: 80\n");
+        let mut xml = Xml::default();
+        BellandeFormat.write_with(&value, &mut xml);
+        assert_eq!(
+            xml.0,
+            "<map><server><map><hosts><list len=\"2\"><item>a</item><item><map><port>80</port></map></item></list></hosts></map></server></map>"
+        );
+    }
+
+    #[test]
+    fn appends_to_a_caller_owned_buffer() {
+        let mut buf = String::from("# generated\n");
+        BellandeFormat.write_bellande_to_string(&parse("a: 1\n"), &mut buf);
+        buf.push('\n');
+        BellandeFormat.write_bellande_to_string(&parse("b:\n  - x\n"), &mut buf);
+        assert_eq!(buf, "# generated\na: 1\nb:\n  - x");
+
+        let options = WriteOptions {
+            shebang: Some("#!/usr/bin/env app".to_string()),
+            header: Some("# license".to_string()),
+            document_start: true,
+            lowercase_keys: true,
+            quote_style: QuotePolicy::Always,
+            ..WriteOptions::default()
+        };
+        let mut buf = String::new();
+        BellandeFormat
+            .write_bellande_to_string_with_options(&parse("Name: demo\n"), &mut buf, &options)
+            .unwrap();
+        assert_eq!(buf, "#!/usr/bin/env app\n# license\n---\nname: \"demo\"");
+        assert!(BellandeFormat
+            .write_bellande_to_string_with_options(&parse("a: 1\nA: 2\n"), &mut buf, &options)
+            .is_err());
+    }
+
+    #[test]
+    fn splits_and_combines_top_level_sections() {
+        let value = parse("server:\n  port: 80\nlog:\n  level: info\nname: demo\n");
+        let sections = value.split_sections();
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections["server"], parse("port: 80\n"));
+        assert_eq!(sections["name"], string("demo"));
+        assert_eq!(BellandeValue::combine_sections(sections), value);
+        assert!(string("x").split_sections().is_empty());
+    }
+
+    #[test]
+    fn collects_every_value_of_a_type() {
+        let value = parse("b: [x, 2, 1.5, true]\na: y\nc:\n  d: 3\n  e: false\n  f: null\n");
+        assert_eq!(value.all_strings(), ["y", "x"]);
+        assert_eq!(value.all_integers(), [2, 3]);
+        assert_eq!(value.all_floats(), [1.5]);
+        assert_eq!(value.all_booleans(), [true, false]);
+    }
+
+    #[test]
+    fn keeps_deep_blocks_raw_until_materialized() {
+        let source =
+            "name: x\nserver:\n  port: 80\n  # tls\n  tls:\n    on: true\nlist:\n  - a\nafter: 1\n";
+        let shallow = ParseOptions {
+            max_parse_depth_materialized: Some(1),
+            ..ParseOptions::default()
+        };
+        let mut value = BellandeFormat
+            .parse_bellande_str_with_options(source, &shallow)
+            .unwrap();
+        assert_eq!(
+            value,
+            map(vec![
+                ("name", string("x")),
+                (
+                    "server",
+                    BellandeValue::Raw("port: 80\n# tls\ntls:\n  on: true".to_string())
+                ),
+                ("list", BellandeValue::Raw("- a".to_string())),
+                ("after", BellandeValue::Integer(1)),
+            ])
+        );
+
+        let written = BellandeFormat.to_bellande_string(&value, 0, &WriteOptions::default());
+        assert_eq!(parse(&written), parse(source));
+
+        BellandeFormat.materialize(&mut value, &shallow).unwrap();
+        assert_eq!(
+            value.get_path("server.tls"),
+            Some(&BellandeValue::Raw("on: true".to_string()))
+        );
+        BellandeFormat
+            .materialize(&mut value, &ParseOptions::default())
+            .unwrap();
+        assert_eq!(value, parse(source));
+    }
+
+    #[test]
+    fn applies_merge_patches_as_in_rfc_7386() {
+        // The examples of RFC 7386, appendix A.
+        for (target, patch, result) in [
+            ("{a: b}", "{a: c}", "{a: c}"),
+            ("{a: b}", "{b: c}", "{a: b, b: c}"),
+            ("{a: b}", "{a: null}", "{}"),
+            ("{a: b, b: c}", "{a: null}", "{b: c}"),
+            ("{a: [b]}", "{a: c}", "{a: c}"),
+            ("{a: c}", "{a: [b]}", "{a: [b]}"),
+            ("{a: {b: c}}", "{a: {b: d, c: null}}", "{a: {b: d}}"),
+            ("{a: [{b: c}]}", "{a: [1]}", "{a: [1]}"),
+            ("[a, b]", "[c, d]", "[c, d]"),
+            ("{a: b}", "[c]", "[c]"),
+            ("{a: foo}", "null", "null"),
+            ("{a: foo}", "bar", "bar"),
+            ("{e: null}", "{a: 1}", "{e: null, a: 1}"),
+            ("[1, 2]", "{a: b, c: null}", "{a: b}"),
+            ("{}", "{a: {bb: {ccc: null}}}", "{a: {bb: {}}}"),
+        ] {
+            let mut value = parse(target);
+            value.apply_merge_patch(&parse(patch));
+            assert_eq!(value, parse(result), "{} + {}", target, patch);
+        }
+
+        let base = parse("a: 1\nb:\n  c: 2\n  d: 3\n");
+        let current = parse("a: 1\nb:\n  c: 4\ne: 5\n");
+        let mut patched = base.clone();
+        patched.apply_merge_patch(&current.to_patch(&base));
+        assert_eq!(patched, current);
+    }
+
+    #[test]
+    fn finds_the_first_differing_path() {
+        let old = parse("server:\n  port: 80\n  hosts: [a, b]\nname: x\n");
+        assert_eq!(old.first_diff(&old.clone()), None);
+        let new = parse("server:\n  port: 443\n  hosts: [a, c]\nname: y\n");
+        assert_eq!(old.first_diff(&new).as_deref(), Some("name"));
+        let new = parse("server:\n  port: 443\n  hosts: [a, c]\nname: x\n");
+        assert_eq!(old.first_diff(&new).as_deref(), Some("server.hosts.1"));
+        let new = parse("server:\n  port: 80\n  hosts: [a, b, c]\nname: x\n");
+        assert_eq!(old.first_diff(&new).as_deref(), Some("server.hosts.2"));
+        let new = parse("server:\n  port: 80\n  hosts: [a, b]\n");
+        assert_eq!(old.first_diff(&new).as_deref(), Some("name"));
+        assert_eq!(string("a").first_diff(&old).as_deref(), Some(""));
+    }
+
+    #[test]
+    fn reports_differences_line_by_line() {
+        let old = parse(
+            "server:\n  port: 80\n  host: a\nlegacy:\n  flag: true\nhosts: [a, b]\nmode: {x: 1}\n",
+        );
+        let new = parse(
+            "server:\n  port: 443\n  host: a\n  tls: true\nhosts: [a, c, d]\nmode: fast\nempty: []\n",
+        );
+        assert_eq!(
+            old.diff_report(&new),
+            "+ empty: []\n\
+             ~ hosts.1: b -> c\n\
+             + hosts.2: d\n\
+             - legacy.flag\n\
+             - mode.x\n\
+             + mode: fast\n\
+             ~ server.port: 80 -> 443\n\
+             + server.tls: true\n"
+        );
+        assert_eq!(old.diff_report(&old), "");
+        assert_eq!(
+            string("a").diff_report(&string("b c")),
+            "~ (root): a -> \"b c\"\n"
+        );
+        assert_eq!(
+            parse("y: 1\n").diff_report(&parse("y: 1\nx: {a.b: 1}\n")),
+            "+ x.a.b: 1\n"
+        );
+    }
+
+    #[test]
+    fn reads_and_writes_a_custom_key_value_separator() {
+        let options = ParseOptions {
+            kv_separator: '=',
+            ..ParseOptions::default()
+        };
+        let value = BellandeFormat
+            .parse_bellande_str_with_options(
+                "name = demo\ntitle = \"a = b\"\nquery = a=b\nurl = http://x:80\nserver =\n  port = 80\nusers =\n  - name = ann\n",
+                &options,
+            )
+            .unwrap();
+        assert_eq!(
+            value,
+            map(vec![
+                ("name", string("demo")),
+                ("title", string("a = b")),
+                ("query", string("a=b")),
+                ("url", string("http://x:80")),
+                ("server", map(vec![("port", BellandeValue::Integer(80))])),
+                (
+                    "users",
+                    BellandeValue::List(vec![map(vec![("name", string("ann"))])])
+                ),
+            ])
+        );
+
+        let write = WriteOptions {
+            kv_separator: Some('='),
+            ..WriteOptions::default()
+        };
+        let value = map(vec![("name", string("demo")), ("query", string("a=b"))]);
+        let written = BellandeFormat.to_bellande_string(&value, 0, &write);
+        let mut lines: Vec<&str> = written.lines().collect();
+        lines.sort();
+        assert_eq!(lines, ["name = demo", "query = \"a=b\""]);
+        assert_eq!(
+            BellandeFormat
+                .parse_bellande_str_with_options(&written, &options)
+                .unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn writes_list_items_indented_or_aligned() {
+        let value = map(vec![(
+            "users",
+            BellandeValue::List(vec![
+                map(vec![(
+                    "tags",
+                    BellandeValue::List(vec![string("a"), BellandeValue::List(vec![string("b")])]),
+                )]),
+                string("c"),
+            ]),
+        )]);
+        for (list_indent, expected) in [
+            (
+                ListIndent::Indented,
+                "users:\n  - tags:\n      - a\n      - - b\n  - c",
+            ),
+            (ListIndent::Aligned, "users:\n- tags:\n  - a\n  - - b\n- c"),
+        ] {
+            let options = WriteOptions {
+                list_indent,
+                ..WriteOptions::default()
+            };
+            let written = BellandeFormat.to_bellande_string(&value, 0, &options);
+            assert_eq!(written, expected);
+            assert_eq!(parse(&written), value);
+        }
+
+        assert_eq!(
+            parse("a:\n- 1\n- 2\nb: x\n"),
+            map(vec![
+                (
+                    "a",
+                    BellandeValue::List(vec![BellandeValue::Integer(1), BellandeValue::Integer(2)])
+                ),
+                ("b", string("x")),
+            ])
+        );
+    }
+
+    #[test]
+    fn writes_floats_that_read_back_bit_for_bit() {
+        for f in [
+            0.1,
+            0.1 + 0.2,
+            1.0 / 3.0,
+            -0.0,
+            1e-7,
+            1e16,
+            2f64.powi(60),
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            5e-324,
+        ] {
+            let written = BellandeFormat.to_bellande_string(
+                &map(vec![("x", BellandeValue::Float(f))]),
+                0,
+                &WriteOptions::default(),
+            );
+            match parse(&written).get_path("x") {
+                Some(BellandeValue::Float(read)) => {
+                    assert_eq!(read.to_bits(), f.to_bits(), "{}", written)
+                }
+                other => panic!("{} read back as {:?}", written, other),
+            }
+        }
+    }
+
+    #[test]
+    fn writes_tags_that_read_back_without_inference() {
+        let value = map(vec![
+            ("name", string("demo")),
+            ("port", string("8080")),
+            ("retries", BellandeValue::Integer(3)),
+            ("ratio", BellandeValue::Float(2.0)),
+            ("tls", BellandeValue::Boolean(true)),
+            ("empty", string("")),
+            ("none", BellandeValue::Null),
+            (
+                "tags",
+                BellandeValue::List(vec![string("a b"), BellandeValue::Integer(1)]),
+            ),
+        ]);
+        let options = WriteOptions {
+            emit_tags: true,
+            ..WriteOptions::default()
+        };
+        let written = BellandeFormat.to_bellande_string(&value, 0, &options);
+        let mut lines: Vec<&str> = written.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "  - !int 1",
+                "  - !str \"a b\"",
+                "empty: !str \"\"",
+                "name: !str demo",
+                "none: !null",
+                "port: !str \"8080\"",
+                "ratio: !float 2.0",
+                "retries: !int 3",
+                "tags:",
+                "tls: !bool true",
+            ]
+        );
+        assert_eq!(parse(&written), value);
+        let untyped = ParseOptions {
+            infer_types: false,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            BellandeFormat
+                .parse_bellande_str_with_options(&written, &untyped)
+                .unwrap(),
+            value
+        );
+
+        let special = BellandeValue::List(vec![
+            BellandeValue::Float(f64::INFINITY),
+            BellandeValue::Float(f64::NEG_INFINITY),
+            BellandeValue::Float(f64::NAN),
+        ]);
+        let written = BellandeFormat.to_bellande_string(&special, 0, &options);
+        assert_eq!(written, "- !float inf\n- !float -inf\n- !float NaN");
+        let read = BellandeFormat
+            .parse_bellande_str_with_options(&written, &untyped)
+            .unwrap();
+        let floats: Vec<f64> = read
+            .as_list()
+            .unwrap()
+            .iter()
+            .map(|item| match item {
+                BellandeValue::Float(f) => *f,
+                other => panic!("expected a float, found {:?}", other),
+            })
+            .collect();
+        assert_eq!(floats[..2], [f64::INFINITY, f64::NEG_INFINITY]);
+        assert!(floats[2].is_nan());
+        assert_eq!(parse("a: inf\n"), map(vec![("a", string("inf"))]));
+    }
+
+    #[test]
+    fn writes_comments_naming_inferred_types() {
+        let value = map(vec![
+            ("port", BellandeValue::Integer(8080)),
+            ("zip", string("007")),
+            ("ratio", BellandeValue::Float(0.5)),
+            ("tls", BellandeValue::Boolean(true)),
+            ("note", string("a # b")),
+            ("none", BellandeValue::Null),
+            (
+                "hosts",
+                BellandeValue::List(vec![string("a"), BellandeValue::List(vec![])]),
+            ),
+        ]);
+        let options = WriteOptions {
+            type_comments: true,
+            ..WriteOptions::default()
+        };
+        let written = BellandeFormat.to_bellande_string(&value, 0, &options);
+        let mut lines: Vec<&str> = written.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "  - []",
+                "  - a  # string",
+                "hosts:",
+                "none: null  # null",
+                "note: \"a # b\"  # string",
+                "port: 8080  # integer",
+                "ratio: 0.5  # float",
+                "tls: true  # boolean",
+                "zip: \"007\"  # string",
+            ]
+        );
+        assert_eq!(parse(&written), value);
+
+        let root = BellandeFormat.to_bellande_string(&BellandeValue::Integer(1), 0, &options);
+        assert_eq!(root, "1  # integer");
+        assert_eq!(parse(&root), BellandeValue::Integer(1));
+    }
+
+    #[test]
+    fn quotes_every_string_when_asked() {
+        let value = map(vec![
+            ("name", string("demo")),
+            ("port", BellandeValue::Integer(8080)),
+            (
+                "tags",
+                BellandeValue::List(vec![string("a b"), BellandeValue::Null]),
+            ),
+        ]);
+        let options = WriteOptions {
+            quote_style: QuotePolicy::Always,
+            ..WriteOptions::default()
+        };
+        let written = BellandeFormat.to_bellande_string(&value, 0, &options);
+        let mut lines: Vec<&str> = written.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "  - \"a b\"",
+                "  - null",
+                "name: \"demo\"",
+                "port: 8080",
+                "tags:"
+            ]
+        );
+        assert_eq!(parse(&written), value);
+    }
+
+    #[test]
+    fn stringifies_every_leaf() {
+        let value =
+            parse("port: 8080\nratio: 1.5\ntls: true\nnone: null\nname: \"8080\"\nhosts: [a, 2]\n");
+        assert_eq!(
+            value.stringify_leaves(),
+            map(vec![
+                ("port", string("8080")),
+                ("ratio", string("1.5")),
+                ("tls", string("true")),
+                ("none", string("null")),
+                ("name", string("8080")),
+                ("hosts", BellandeValue::List(vec![string("a"), string("2")])),
+            ])
+        );
+    }
+
+    #[test]
+    fn reports_every_unresolvable_reference() {
+        let value =
+            parse("a: ${b}\nb: ${nope}\nc: ${d}\nd: x${c}\ne: x${list}\nlist: [1]\nok: ${a}\n");
+        let errors: Vec<String> = value
+            .validate_references()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "`b`: `${nope}` refers to a missing value",
+                "`c`: reference cycle: c -> d -> c",
+                "`e`: `${list}` is a list and cannot be part of a string",
+            ]
+        );
+        assert!(parse("a: ${b.c}\nb:\n  c: ${d}\nd: 1\n")
+            .validate_references()
+            .is_ok());
+    }
+
+    #[test]
+    fn interpolates_references_to_other_keys() {
+        let options = ParseOptions {
+            interpolate: true,
+            ..ParseOptions::default()
+        };
+        let read =
+            |content: &str| BellandeFormat.parse_bellande_str_with_options(content, &options);
+        let value = read(
+            "base_url: http://${server.host}:${server.port}/\nport: ${server.port}\nserver:\n  host: ${hosts.0}\n  port: 8080\nhosts: [example.com]\ncopy: ${server}\nhome: ${HOME:-/root}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            value.get_path("base_url"),
+            Some(&string("http://example.com:8080/"))
+        );
+        assert_eq!(value.get_path("port"), Some(&BellandeValue::Integer(8080)));
+        assert_eq!(value.get_path("copy"), value.get_path("server"));
+        assert_eq!(value.get_path("home"), Some(&string("${HOME:-/root}")));
+
+        let err = read("a: ${b}\nb: x${a}\n").unwrap_err();
+        assert_eq!(err.to_string(), "`a`: reference cycle: a -> b -> a");
+        let err = read("a: ${nope}\n").unwrap_err();
+        assert_eq!(err.to_string(), "`a`: `${nope}` refers to a missing value");
+        let err = read("a: [1]\nb: x${a}\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`b`: `${a}` is a list and cannot be part of a string"
+        );
+        assert_eq!(
+            BellandeFormat
+                .parse_bellande_str("a: ${b}\nb: 1\n")
+                .unwrap(),
+            map(vec![
+                ("a", string("${b}")),
+                ("b", BellandeValue::Integer(1))
+            ])
+        );
+    }
+
+    #[test]
+    fn summarizes_large_values() {
+        let value = map(vec![
+            ("b", string("abcdefgh")),
+            (
+                "a",
+                BellandeValue::List((1..=5).map(BellandeValue::Integer).collect()),
+            ),
+            ("c", string("é")),
+        ]);
+        assert_eq!(
+            value.summary(2, 3),
+            map(vec![
+                (
+                    "a",
+                    BellandeValue::List(vec![
+                        BellandeValue::Integer(1),
+                        BellandeValue::Integer(2),
+                        string("… 3 more"),
+                    ])
+                ),
+                ("b", string("abc…")),
+                ("…", string("1 more")),
+            ])
+        );
+        assert_eq!(value.summary(5, 10), value);
+    }
+
+    #[test]
+    fn iterates_over_scalar_leaves() {
+        let value = parse("b:\n  y: 2\n  x: 1\n  none: []\na: [t, {k: null}]\nc: {}\n");
+        let leaves: Vec<(String, &BellandeValue)> = value.leaves().collect();
+        assert_eq!(
+            leaves,
+            [
+                ("a.0".to_string(), &string("t")),
+                ("a.1.k".to_string(), &BellandeValue::Null),
+                ("b.x".to_string(), &BellandeValue::Integer(1)),
+                ("b.y".to_string(), &BellandeValue::Integer(2)),
+            ]
+        );
+        assert_eq!(
+            string("root").leaves().collect::<Vec<_>>(),
+            [(String::new(), &string("root"))]
+        );
+    }
+
+    #[test]
+    fn finds_keys_matching_a_predicate() {
+        let value = parse(
+            "password: a\ndb:\n  db_password: b\n  user: c\nusers:\n  - password: d\n  - e\n",
+        );
+        let found = value.find_keys(|key| key.contains("password"));
+        assert_eq!(
+            found,
+            [
+                ("db.db_password".to_string(), &string("b")),
+                ("password".to_string(), &string("a")),
+                ("users.0.password".to_string(), &string("d")),
+            ]
+        );
+        assert!(value.find_keys(|key| key == "0").is_empty());
+
+        let nested = parse(
+            "a:
+  a: 1
+",
+        );
+        assert_eq!(
+            nested
+                .find_keys(|key| key == "a")
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>(),
+            ["a", "a.a"]
+        );
+    }
+
+    #[test]
+    fn expects_values_of_a_given_type() {
+        let value = parse("server:\n  hosts:\n    - a\n  port: 80\n");
+        assert_eq!(
+            value.expect_type("server.hosts", "list").unwrap(),
+            &BellandeValue::List(vec![string("a")])
+        );
+        assert_eq!(
+            value.expect_type("server", "map").unwrap().type_name(),
+            "map"
+        );
+        assert!(matches!(
+            value.expect_type("server.tls", "boolean"),
+            Err(BellandeError::MissingPath(_))
+        ));
+        assert_eq!(
+            value
+                .expect_type("server.port", "string")
+                .unwrap_err()
+                .to_string(),
+            "`server.port`: expected string, found integer"
+        );
+    }
+
+    #[test]
+    fn writes_floats_beyond_thresholds_in_scientific_notation() {
+        let value = map(vec![
+            ("large", BellandeValue::Float(1e9)),
+            ("small", BellandeValue::Float(-2.5e-7)),
+            ("plain", BellandeValue::Float(1500.25)),
+            ("zero", BellandeValue::Float(0.0)),
+            ("count", BellandeValue::Integer(5_000_000_000)),
+        ]);
+        let options = WriteOptions {
+            scientific_notation: Some(ScientificNotation {
+                at_least: 1e6,
+                below: 1e-4,
+            }),
+            ..WriteOptions::default()
+        };
+        let written = BellandeFormat.to_bellande_string(&value, 0, &options);
+        let mut lines: Vec<&str> = written.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "count: 5000000000",
+                "large: 1e9",
+                "plain: 1500.25",
+                "small: -2.5e-7",
+                "zero: 0.0"
+            ]
+        );
+        assert_eq!(parse(&written), value);
+    }
+
+    #[test]
+    fn aligns_colons_within_each_map() {
+        let value = map(vec![
+            ("name", string("api")),
+            (
+                "server",
+                map(vec![
+                    ("host", string("a")),
+                    ("timeout", BellandeValue::Integer(5)),
+                ]),
+            ),
+            ("an_unusually_long_key_name", BellandeValue::Boolean(true)),
+        ]);
+        let options = WriteOptions {
+            align_colons: true,
+            ..WriteOptions::default()
+        };
+        let written = BellandeFormat.to_bellande_string(&value, 0, &options);
+        let mut lines: Vec<&str> = written.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "  host   : a",
+                "  timeout: 5",
+                "an_unusually_long_key_name: true",
+                "name  : api",
+                "server:",
+            ]
+        );
+        assert_eq!(parse(&written), value);
+    }
+
+    #[test]
+    fn parses_documents_rooted_at_a_list_or_scalar() {
+        let list = parse("# hosts\n- a\n- name: b\n  port: 1\n-\n  - c\n");
+        assert_eq!(
+            list,
+            BellandeValue::List(vec![
+                string("a"),
+                map(vec![
+                    ("name", string("b")),
+                    ("port", BellandeValue::Integer(1))
+                ]),
+                BellandeValue::List(vec![string("c")]),
+            ])
+        );
+        assert_eq!(round_trip(&list), list);
+
+        assert_eq!(parse("42\n"), BellandeValue::Integer(42));
+        assert_eq!(parse("---\n\"a: b\" # note\n"), string("a: b"));
+        assert_eq!(
+            parse("[1,\n 2]\n# end\n"),
+            BellandeValue::List(vec![BellandeValue::Integer(1), BellandeValue::Integer(2)])
+        );
+        assert_eq!(round_trip(&string("hello")), string("hello"));
+        assert_eq!(parse(""), map(vec![]));
+
+        let err = BellandeFormat
+            .parse_bellande_str("42\nkey: 1\n")
+            .unwrap_err();
+        assert!(matches!(err, BellandeError::Parse { line: 2, .. }));
+        let err = BellandeFormat
+            .parse_bellande_str("- a\nkey: 1\n")
+            .unwrap_err();
+        assert!(matches!(err, BellandeError::Parse { line: 2, .. }));
+    }
+
+    #[test]
+    fn lenient_parsing_skips_only_the_broken_line() {
+        let options = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+        let (value, warnings) = BellandeFormat
+            .parse_bellande_str_with_warnings("a: 1\nb: [1, 2\nc: 3\n- x\nd: 4\n", &options)
+            .unwrap();
+        assert_eq!(value, parse("a: 1\nc: 3\nd: 4\n"));
+        let lines: Vec<usize> = warnings.iter().map(|warning| warning.line).collect();
+        assert_eq!(lines, [2, 4]);
+
+        let options = ParseOptions {
+            max_nodes: Some(2),
+            ..options
+        };
+        assert!(matches!(
+            BellandeFormat.parse_bellande_str_with_warnings("a: 1\nb: 2\nc: 3\n", &options),
+            Err(BellandeError::LimitExceeded(_))
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn lenient_parsing_reports_soft_problems() {
+        let path = temp_path("lenient.bellande");
+        fs::write(
+            &path,
+            "name: a\nname: b\nserver:\n    host: x\n  port: 80\noops\ncount: 1,000\nversion: 1.2.3\n",
+        )
+        .unwrap();
+        let (value, warnings) = BellandeFormat.parse_bellande_lenient(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            value,
+            parse("name: b\nserver:\n  host: x\n  port: 80\ncount: \"1,000\"\nversion: 1.2.3\n")
+        );
+        let lines: Vec<(usize, &str)> = warnings
+            .iter()
+            .map(|warning| (warning.line, warning.message.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (2, "duplicate key `name` overwrites the earlier value"),
+                (
+                    5,
+                    "indentation of 2 does not match the 4 of earlier siblings"
+                ),
+                (6, "skipped: expected `key: value` or `- item`"),
+                (7, "`1,000` looks like a number but is read as a string"),
+            ]
+        );
+        assert!(BellandeFormat.parse_bellande_str("oops\nkey: 1\n").is_err());
     }
 }